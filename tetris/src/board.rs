@@ -1,11 +1,47 @@
 #![allow(dead_code)]
 
 use crate::constants::board_constants::*;
+use crate::constants::piece_constants::{NUM_ROTATE_STATES, RELATIVE_CORNERS};
 use crate::constants::types::*;
+use crate::constants::hash_constants::*;
 use crate::piece::Piece;
 use crate::point_vector::{Point, PointVector};
+use itertools::Itertools;
 use std::fmt::{Display, Formatter};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TSlotKind {
+    // pointing down into an open slot, a tsd or tss
+    Flat,
+    // under an overhang, the t has to be tucked or spun in (imperial cross, the lower tsd of an stsd)
+    Covered,
+    // on its side for a double, the second spin of an stsd
+    Stsd,
+    // on its side under an overhang, a vertical t-spin triple
+    Triple,
+    // on its side for a single
+    Vertical,
+}
+
+impl TSlotKind {
+    // how likely the t gets in as the slot stands, a flat slot only needs a drop and a spin
+    pub fn odds(&self) -> f32 {
+        match self {
+            TSlotKind::Flat => 1.0,
+            TSlotKind::Stsd | TSlotKind::Triple => 0.75,
+            TSlotKind::Covered | TSlotKind::Vertical => 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TSlot {
+    pub kind: TSlotKind,
+    // the t as it locks in the slot
+    pub piece: Piece,
+    pub lines: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct Board {
     arr: BoardArray,
@@ -29,7 +65,7 @@ impl Display for Board {
                     write!(f, "□ ")?
                 }
             }
-            write!(f, "\n")?
+            writeln!(f)?
         }
         Ok(())
     }
@@ -47,7 +83,7 @@ impl Board {
     }
 
     pub fn get_heights(&self) -> Vec<usize> {
-        (0..BOARD_WIDTH).into_iter().map(|col| self.get_height(col)).collect()
+        (0..BOARD_WIDTH).map(|col| self.get_height(col)).collect()
     }
 
     fn set(&mut self, row: usize, col: usize, item: usize) {
@@ -67,7 +103,7 @@ impl Board {
     }
 
     pub fn get_row(&self, row: usize) -> Row {
-        (0..BOARD_WIDTH).into_iter().map(|col| self._get(row, col) << col).sum()
+        (0..BOARD_WIDTH).map(|col| self._get(row, col) << col).sum()
     }
 
     pub fn get_col(&self, col: usize) -> Column {
@@ -79,11 +115,11 @@ impl Board {
     }
 
     pub fn get_max_height(&self) -> usize {
-        (0..BOARD_WIDTH).into_iter().map(|col| self.get_height(col)).max().unwrap()
+        (0..BOARD_WIDTH).map(|col| self.get_height(col)).max().unwrap()
     }
 
     pub fn get_min_height(&self) -> usize {
-         (0..BOARD_WIDTH).into_iter().map(|col| self.get_height(col)).min().unwrap()
+         (0..BOARD_WIDTH).map(|col| self.get_height(col)).min().unwrap()
     }
 
     // setters
@@ -122,11 +158,30 @@ impl Board {
         let mut new_row = new_row + (1 << BOARD_WIDTH);
         while col < BOARD_WIDTH && new_row > 0 {
             self.set(row, col, new_row % 2);
-            new_row = new_row >> 1;
+            new_row >>= 1;
             col += 1;
         }
     }
 
+    pub fn add_garbage(&mut self, lines: usize, hole: usize) -> bool {
+        // pushes every column up and fills the bottom rows except for the hole,
+        // returns false if any minos were pushed off the top of the board
+        let lines = lines.min(BOARD_HEIGHT);
+        let mask = (1 << BOARD_HEIGHT) - 1;
+        let garbage = (1 << lines) - 1;
+        let mut out = true;
+
+        for (col, column) in self.arr.iter_mut().enumerate() {
+            let shifted = *column << lines;
+            out &= shifted & !mask == 0;
+            *column = shifted & mask;
+            if col != hole {
+                *column |= garbage;
+            }
+        }
+        out
+    }
+
     pub fn remove_row(&mut self, row: usize) {
         for col in 0..BOARD_WIDTH {
             self.remove(row, col);
@@ -160,8 +215,7 @@ impl Board {
             && locations
                 .unwrap()
                 .iter()
-                .map(|&Point(row, col)| self.get(row as usize, col as usize))
-                .any(|x| x)
+                .any(|&Point(row, col)| self.get(row as usize, col as usize))
     }
 
     pub fn piece_grounded(&self, piece: &Piece) -> bool {
@@ -171,6 +225,20 @@ impl Board {
         true
     }
 
+    pub fn drop_distance(&self, piece: &Piece) -> usize {
+        // how far each mino can fall is the gap to the highest filled cell below it in its column
+        piece
+            .abs_locations()
+            .unwrap()
+            .iter()
+            .map(|&Point(row, col)| {
+                let below = self.get_col(col as usize) & ((1 << row) - 1);
+                row as usize - (usize::BITS - below.leading_zeros()) as usize
+            })
+            .min()
+            .unwrap()
+    }
+
     pub fn piece_valid_location(&self, piece: &Piece) -> bool {
         self.piece_in_bounds(piece) && !self.piece_collision(piece)
     }
@@ -193,7 +261,7 @@ impl Board {
     }
 
     // versus
-    pub fn top_out(&mut self, piece: &Piece, next: &Piece) -> bool {
+    pub fn top_out(&mut self, piece: &Piece, next: &Piece, max_height: usize) -> bool {
         self.set_piece(piece);
         if self.piece_collision(next) {
             return true;
@@ -203,7 +271,7 @@ impl Board {
             .abs_locations()
             .unwrap()
             .iter()
-            .any(|&x| x.0 < MAX_PLACE_HEIGHT as i8)
+            .any(|&x| x.0 < max_height as i8)
     }
 
     pub fn all_clear(&self) -> bool {
@@ -236,7 +304,6 @@ impl Board {
 
     fn all_full_rows(&self) -> Vec<usize> {
         (0..self.get_max_height())
-            .into_iter()
             .filter(|x| self.full_row(*x))
             .collect()
     }
@@ -314,6 +381,87 @@ impl Board {
 
     }
 
+    // every spot a t could lock into with a full spin that clears lines
+    pub fn t_slots(&self) -> Vec<TSlot> {
+        let mut out = Vec::new();
+        let top = (self.get_max_height() + 1).min(BOARD_HEIGHT - 1);
+        let height = |col: i8| if (0..BOARD_WIDTH as i8).contains(&col) { self.get_height(col as usize) } else { top };
+        for col in 0..BOARD_WIDTH {
+            let c = col as i8;
+            // a corner above the center is filled, and a t can't sink more than a few rows under the surface
+            let high = height(c - 1).max(height(c + 1)).min(top + 1).saturating_sub(1);
+            let low = height(c - 1).min(height(c)).min(height(c + 1)).saturating_sub(3);
+            for row in low..high {
+                // three corners around the center is the cheap check, most spots stop here
+                if self.get(row, col) || self.corners(row as i8, col as i8) < 3 {
+                    continue;
+                }
+                for rotation_state in 0..NUM_ROTATE_STATES {
+                    let piece = Piece {
+                        rotation_state,
+                        center: Point(row as i8, col as i8),
+                        ..Piece::new(6)
+                    };
+                    out.extend(self.t_slot_at(piece));
+                }
+            }
+        }
+        out
+    }
+
+    fn t_slot_at(&self, piece: Piece) -> Option<TSlot> {
+        let cells = piece.abs_locations()?;
+        if cells.iter().any(|&Point(row, col)| self.filled(row, col)) {
+            return None;
+        }
+        if !cells.iter().any(|&Point(row, col)| self.filled(row - 1, col)) {
+            return None;
+        }
+
+        // a row clears when the t fills every gap the board leaves in it
+        let lines = cells
+            .iter()
+            .map(|p| p.0)
+            .unique()
+            .filter(|&row| (0..BOARD_WIDTH as i8).all(|col| self.filled(row, col) || cells.contains(&Point(row, col))))
+            .count();
+
+        // a triple kicks in for a full spin whatever the corners in front
+        let (front, _) = RELATIVE_CORNERS[piece.rotation_state];
+        let front = front
+            .iter()
+            .filter(|v| self.filled(piece.center.0 + v.0, piece.center.1 + v.1))
+            .count();
+        if lines == 0 || (front < 2 && lines < 3) {
+            return None;
+        }
+
+        // the overhang corner of a normal tsd is fine, a roof over the middle or both sides isn't
+        let Point(row, col) = piece.center;
+        let roofed = self.get_height(col as usize) > row as usize + 1
+            || (self.filled(row + 1, col - 1) && self.filled(row + 1, col + 1));
+        let kind = match (piece.rotation_state, lines) {
+            (_, 3) => TSlotKind::Triple,
+            (2, _) if !roofed => TSlotKind::Flat,
+            (0 | 2, _) => TSlotKind::Covered,
+            (_, 2) => TSlotKind::Stsd,
+            _ => TSlotKind::Vertical,
+        };
+        Some(TSlot { kind, piece, lines })
+    }
+
+    fn corners(&self, row: i8, col: i8) -> usize {
+        [(1, 1), (1, -1), (-1, 1), (-1, -1)]
+            .iter()
+            .filter(|(r, c)| self.filled(row + r, col + c))
+            .count()
+    }
+
+    fn filled(&self, row: i8, col: i8) -> bool {
+        // walls and the floor count as filled
+        row < 0 || col < 0 || !Board::in_bounds(row as usize, col as usize) || self.get(row as usize, col as usize)
+    }
+
     pub fn get_max_height_difference(&self) -> usize {
         self.get_max_height() - self.get_min_height()
     }
@@ -337,7 +485,15 @@ impl Board {
             checkerboard_parity = checkerboard_parity + ((i%2) as i32) * (blacks - whites)
                 - (((i+1)%2) as i32) * (blacks - whites);
         }
-        return (checkerboard_parity == 0, col_parity == 0)
+        (checkerboard_parity == 0, col_parity == 0)
+    }
+
+    // a full board hash, worked out from every column on each call
+    pub fn state_hash(&self) -> u64 {
+        self.arr
+            .iter()
+            .zip(COLUMN_KEYS)
+            .fold(0, |hash, (&col, key)| hash ^ mix(col as u64 ^ key))
     }
 
     pub fn get_mino_count(&self) -> usize {
@@ -345,7 +501,7 @@ impl Board {
         for col in self.get_arr(){
             out += col.count_ones() as usize;
         }
-        return out;
+        out
     }
 
     // other
@@ -362,7 +518,7 @@ impl Board {
                     out.push_str("□ ");
                 }
             }
-            out.push_str("\n");
+            out.push('\n');
         }
 
         out
@@ -385,6 +541,84 @@ mod board_tests {
         println!("{}", board);
     }
 
+    #[test]
+    fn test_drop_distance() {
+        let mut board = Board::new();
+        board.add_list(vec![Point(3, 4), Point(12, 5), Point(0, 6)]);
+
+        // a T at spawn, sitting over a stack and a mino above it
+        let mut piece = Piece::new(6);
+        assert_eq!(board.drop_distance(&piece), 21 - 13);
+        piece.set_row(10);
+        assert_eq!(board.drop_distance(&piece), 10 - 4);
+        piece.set_col(1);
+        assert_eq!(board.drop_distance(&piece), 10);
+    }
+
+    fn from_rows(rows: &[&str]) -> Board {
+        // top row first, x is filled
+        let mut board = Board::new();
+        for (row, line) in rows.iter().rev().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                if c == 'x' {
+                    board.add(row, col);
+                }
+            }
+        }
+        board
+    }
+
+    fn find(board: &Board, kind: TSlotKind) -> Option<TSlot> {
+        board.t_slots().into_iter().find(|slot| slot.kind == kind)
+    }
+
+    #[test]
+    fn test_t_slots() {
+        let tsd = from_rows(&["xxxx......", "xxx...xxxx", "xxxx.xxxxx"]);
+        let slot = find(&tsd, TSlotKind::Flat).unwrap();
+        assert_eq!((slot.piece.center, slot.piece.rotation_state, slot.lines), (Point(1, 4), 2, 2));
+        assert!(find(&tsd, TSlotKind::Covered).is_none());
+
+        let covered = from_rows(&["....x.....", "xxxx......", "xxx...xxxx", "xxxx.xxxxx"]);
+        assert_eq!(find(&covered, TSlotKind::Covered).unwrap().lines, 2);
+        assert!(find(&covered, TSlotKind::Flat).is_none());
+
+        let stsd = from_rows(&["xxxx.xxx..", "xxx..xxxxx", "xxxx.xxxxx"]);
+        let slot = find(&stsd, TSlotKind::Stsd).unwrap();
+        assert_eq!((slot.piece.center, slot.lines), (Point(1, 4), 2));
+
+        let tst = from_rows(&["xxx.......", "xxxx.xxxxx", "xxx..xxxxx", "xxxx.xxxxx"]);
+        let slot = find(&tst, TSlotKind::Triple).unwrap();
+        assert_eq!((slot.piece.center, slot.lines), (Point(1, 4), 3));
+
+        // an imperial cross has overhangs on both sides, the t has to be spun down into it
+        let cross = from_rows(&["xxxx.xxx..", "xxx...xxxx", "xxxx.xxxxx"]);
+        let slot = cross.t_slots().into_iter().max_by_key(|slot| slot.lines).unwrap();
+        assert_eq!((slot.kind, slot.piece.center, slot.piece.rotation_state, slot.lines), (TSlotKind::Covered, Point(1, 4), 2, 2));
+        assert!(find(&cross, TSlotKind::Flat).is_none());
+
+        // the vertical tst still counts under a taller overhang
+        let tall = from_rows(&["xxx.......", "xxx.......", "xxx.......", "xxxx.xxxxx", "xxx..xxxxx", "xxxx.xxxxx"]);
+        assert_eq!(find(&tall, TSlotKind::Triple).unwrap().piece.center, Point(1, 4));
+
+        // a flat stack has nowhere to spin
+        assert!(from_rows(&["xxxxxxxx..", "xxxxxxxx.."]).t_slots().is_empty());
+    }
+
+    #[test]
+    fn test_state_hash() {
+        let mut board = Board::new();
+        assert_eq!(board.state_hash(), Board::new().state_hash());
+        board.add(0, 0);
+        let one = board.state_hash();
+        assert_ne!(one, Board::new().state_hash());
+
+        // the same cells in another column hash differently
+        let mut other = Board::new();
+        other.add(0, 1);
+        assert_ne!(one, other.state_hash());
+    }
+
     #[test]
     fn test_heights() {
         let mut board = Board::new();
@@ -439,6 +673,20 @@ mod board_tests {
         assert_eq!(board.get_heights(), [0, 0, 6, 7, 0, 0, 0, 0, 0, 0]);
     }
 
+    #[test]
+    fn test_garbage() {
+        let mut board = Board::new();
+        board.add(0, 4);
+        assert!(board.add_garbage(2, 3));
+        assert_eq!(board.get_heights(), [2, 2, 2, 0, 3, 2, 2, 2, 2, 2]);
+        assert!(!board.get(0, 3));
+        assert!(board.get(2, 4));
+
+        board.add(BOARD_HEIGHT - 1, 0);
+        assert!(!board.add_garbage(1, 0));
+        assert_eq!(board.get_heights()[0], 3);
+    }
+
     #[test]
     fn test_parity() {
         let mut board = Board::new();
@@ -8,13 +8,21 @@ use crate::game::game_rules_and_data::*;
 use crate::piece::Piece;
 use crate::players::{do_command, do_move_list, Player};
 use crate::weight::Weights;
+use crate::finesse::{self, Finesse};
 use std::fmt::{Display, Formatter};
+use std::collections::HashSet;
 use std::iter::zip;
 use std::{mem, thread, time};
+use std::time::{Duration, Instant};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::Arc;
+use crate::search_tree::{play, Background, Limits, SearchTree};
+use crate::monte_carlo::{self, MonteCarloConfig};
+use crate::perfect_clear::{PcConfig, PerfectClear};
 use std::cmp::Ordering::Equal;
 use futures_util::stream::iter;
 use itertools::{izip, Itertools};
-use crate::communications::Suggestion;
+use crate::protocol::{Candidate, Placement, Suggestion};
 use crate::{Dependency, Opener, OpenerStatus, Point};
 use crate::book::openers;
 use crate::constants::board_constants::BOARD_WIDTH;
@@ -24,7 +32,117 @@ use crate::point_vector::PointVector;
 pub struct Bot {
     game: Game,
     weight: Weights,
-    opener: Opener
+    opener: Opener,
+    finesse: Finesse,
+    search: SearchConfig,
+    strategy: Strategy,
+    perfect_clear: Option<PerfectClear>,
+    pool: Option<Arc<ThreadPool>>,
+    tree: Option<SearchTree>,
+    background: Option<Background>,
+    // what the last move was chosen from, for suggestions
+    lines: Vec<Line>,
+    info: String,
+    // the placement last handed out, nothing moves on until it's played
+    planned: Option<(Piece, Source)>,
+}
+
+// which part of the bot chose a placement
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    PerfectClear,
+    // with the sequence the opener was asked with
+    Opener(PieceOrder),
+    Search,
+}
+
+// a placement and what the search planned after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub placements: PlacementList,
+    pub score: (Score, Score),
+}
+
+impl Line {
+    // played out from the game it was searched from to see what it sends, None if the placement
+    // can't be reached
+    pub fn to_candidate(&self, game: &Game, finesse: &Finesse) -> Option<Candidate> {
+        let mut moves = Bot::inputs_for(game, &self.placements[0], finesse)?;
+        moves.push(game.lock_command());
+
+        let mut game = game.clone();
+        let sent = game.game_data.lines_sent;
+        play(&mut game, &self.placements[0]);
+        let attack = game.game_data.last_sent as u16;
+        for placement in &self.placements[1..] {
+            play(&mut game, placement);
+        }
+
+        Some(Candidate {
+            input_list: Bot::command_list_string(&moves),
+            placement: Placement::from(&self.placements[0]),
+            follow_up: self.placements[1..].iter().map(Placement::from).collect(),
+            board_score: self.score.0,
+            versus_score: self.score.1,
+            attack,
+            planned_attack: game.game_data.lines_sent.saturating_sub(sent),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    Unlimited,
+    Time(Duration),
+    // expanded nodes
+    Nodes(usize),
+}
+
+impl Budget {
+    pub(crate) fn exhausted(&self, start: Instant, nodes: usize) -> bool {
+        match self {
+            Budget::Unlimited => false,
+            Budget::Time(_) => self.out_of_time(start),
+            Budget::Nodes(max) => nodes >= *max,
+        }
+    }
+
+    pub(crate) fn out_of_time(&self, start: Instant) -> bool {
+        matches!(self, Budget::Time(duration) if start.elapsed() >= *duration)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchConfig {
+    pub beam_width: usize,
+    pub prune_depth: usize,
+    pub max_depth: usize,
+    // how much less the versus score of the deepest placement counts
+    pub discount: f32,
+    pub budget: Budget,
+    pub threads: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Strategy {
+    // beam search over the queue as given
+    #[default]
+    Beam,
+    // samples the pieces past the preview instead of trusting the queue
+    MonteCarlo(MonteCarloConfig),
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            beam_width: 50,
+            prune_depth: 1,
+            max_depth: 11,
+            discount: 0.5,
+            budget: Budget::Unlimited,
+            threads: 1,
+        }
+    }
 }
 
 impl Display for Bot {
@@ -40,6 +158,16 @@ impl Default for Bot {
             game: Game::new(None),
             weight: Weights::default(),
             opener: Opener::default(),
+            finesse: Finesse::default(),
+            search: SearchConfig::default(),
+            strategy: Strategy::default(),
+            perfect_clear: None,
+            pool: None,
+            tree: None,
+            background: None,
+            lines: Vec::new(),
+            info: String::new(),
+            planned: None,
         }
     }
 }
@@ -56,6 +184,20 @@ impl Player for Bot {
     fn get_next_move(&mut self) -> CommandList {
         // R, C
         let mut action = vec![];
+        self.lines.clear();
+        self.info.clear();
+        self.planned = None;
+
+        // an all clear in reach beats anything else
+        if let Some(placement) = self.perfect_clear.as_mut().and_then(|pc| pc.next_placement(&self.game)) {
+            if let Ok(mut action) = Bot::moves_to_placement(&self.game, &placement, &self.finesse) {
+                let plan = self.perfect_clear.as_ref().map_or(0, |pc| pc.get_plan().len().saturating_sub(1));
+                self.info = format!("perfect clear, {} placements after this", plan);
+                self.planned = Some((placement, Source::PerfectClear));
+                action.push(self.game.lock_command());
+                return action;
+            }
+        }
 
         if self.opener.status == OpenerStatus::New {
             let mut sequence = vec![self.get_game().active_piece.piece_type];
@@ -66,7 +208,8 @@ impl Player for Bot {
             match self.do_opener() {
                 Ok(m) => {
                     action = m;
-                    action.push(Command::HardDrop);
+                    self.info = "opener".to_string();
+                    action.push(self.game.lock_command());
                     return action;
                 },
                 Err(_) => {
@@ -78,33 +221,62 @@ impl Player for Bot {
 
         // thread::sleep(time::Duration::from_millis(250));
 
-        let (deep_moves, places, deep_scores) = self.move_placement_score(11, &self.weight.clone());
-        let deep_scores: Vec<f32> = deep_scores
-            .iter()
-            .map(|(board, versus)| board + versus)
-            .collect();
+        if let Strategy::MonteCarlo(config) = self.strategy {
+            let Some((moves, placement, score)) = monte_carlo::search(&self.game, &self.weight, &config) else {
+                return vec![self.game.lock_command()];
+            };
+            action = Bot::moves_to_placement(&self.game, &placement, &self.finesse).unwrap_or(moves);
+            self.info = format!("monte carlo, score {:.1}", score);
+            self.planned = Some((placement, Source::Search));
+            action.push(self.game.lock_command());
+            return action;
+        }
 
-        let mut min_score = f32::INFINITY;
-        let mut p = PlacementList::new();
+        self.think(self.search.budget);
+        let Some(tree) = self.tree.as_ref() else {
+            return vec![self.game.lock_command()];
+        };
+        let (depth, ranked) = (tree.get_depth(), tree.ranked(CANDIDATES));
+        let mut lines: Vec<Line> = ranked
+            .into_iter()
+            .map(|(_, placements, score)| Line { placements: placements.clone(), score })
+            .collect();
 
-        for (moves, place, score) in izip!(deep_moves, places, deep_scores) {
-            if score < min_score {
-                min_score = score;
-                action = moves;
-                p = place;
+        // only the move played needs its inputs now, the rest are worked out if they're asked for.
+        // send the cheapest inputs, a line with no way to its placement is dropped
+        let mut action = loop {
+            let Some(best) = lines.first() else {
+                // nowhere to go
+                return vec![self.game.lock_command()];
+            };
+            match Bot::inputs_for(&self.game, &best.placements[0], &self.finesse) {
+                Some(moves) => break moves,
+                None => {
+                    lines.remove(0);
+                }
             }
-        }
+        };
+        action.push(self.game.lock_command());
 
+        let best = lines[0].clone();
+        self.info = format!("beam, depth {}, score {:.1}", depth, best.score.0 + best.score.1);
+        self.planned = Some((best.placements[0], Source::Search));
+        self.lines = lines;
+        action
+    }
 
-        println!("{:?}", action);
-        println!("{}", min_score);
-        println!("{}", p[0]);
-        if min_score < -10000.0{
-            println!("{:?}", p);
+    fn move_played(&mut self) {
+        if let Some((placement, _)) = self.planned.clone() {
+            self.commit(&placement);
         }
+    }
 
-        action.push(Command::HardDrop);
-        action
+    fn suggestion_candidates(&self, game: &Game) -> Vec<Candidate> {
+        self.lines.iter().filter_map(|line| line.to_candidate(game, &self.finesse)).collect()
+    }
+
+    fn suggestion_info(&self) -> String {
+        self.info.clone()
     }
 }
 
@@ -117,6 +289,130 @@ impl Bot {
         }
     }
 
+    pub fn from_weights(game: Game, weight: Weights) -> Self {
+        Self {
+            game,
+            weight,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_finesse(&mut self, finesse: Finesse) {
+        self.finesse = finesse;
+    }
+
+    pub fn get_search_config(&self) -> &SearchConfig {
+        &self.search
+    }
+
+    pub fn set_search_config(&mut self, search: SearchConfig) {
+        self.search = search;
+    }
+
+    pub fn get_strategy(&self) -> &Strategy {
+        &self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        // a beam tree from before would be stale by the time it's used again
+        self.stop_thinking();
+        self.tree = None;
+        self.strategy = strategy;
+    }
+
+    pub fn get_perfect_clear(&self) -> Option<&PerfectClear> {
+        self.perfect_clear.as_ref()
+    }
+
+    // None turns pc mode off
+    pub fn set_perfect_clear(&mut self, config: Option<PcConfig>) {
+        self.perfect_clear = config.map(PerfectClear::new);
+    }
+
+    // searches on a pool shared with other bots, as wide as the pool
+    pub fn set_pool(&mut self, pool: Arc<ThreadPool>) {
+        self.search.threads = pool.current_num_threads();
+        self.pool = Some(pool);
+    }
+
+    pub fn get_pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.pool.as_ref()
+    }
+
+    fn pool(&mut self, threads: usize) -> Option<Arc<ThreadPool>> {
+        // built on first use and kept, a single thread needs no pool
+        if threads <= 1 {
+            return None;
+        }
+        if self.pool.as_ref().is_none_or(|pool| pool.current_num_threads() != threads) {
+            self.pool = ThreadPoolBuilder::new().num_threads(threads).build().ok().map(Arc::new);
+        }
+        self.pool.clone()
+    }
+
+    // thinking
+    pub fn get_search_tree(&self) -> Option<&SearchTree> {
+        self.tree.as_ref()
+    }
+
+    pub fn think(&mut self, budget: Budget) -> bool {
+        let search = self.search.clone();
+        let pool = self.pool(search.threads);
+        let mut tree = self.take_tree();
+        let complete = tree.grow(&search, &self.weight, &Limits::new(budget, None), pool.as_deref());
+        self.tree = Some(tree);
+        complete
+    }
+
+    pub fn think_in_background(&mut self) {
+        let search = self.search.clone();
+        let pool = self.pool(search.threads);
+        let tree = self.take_tree();
+        self.background = Some(Background::spawn(tree, search, self.weight.clone(), pool));
+    }
+
+    pub fn stop_thinking(&mut self) {
+        if let Some(background) = self.background.take() {
+            self.tree = background.stop();
+        }
+    }
+
+    // the game has moved on by this placement, planned or not, so everything planned from before it
+    // moves on with it
+    pub fn commit(&mut self, placement: &Piece) {
+        let planned = self.planned.take();
+        if let Some(pc) = self.perfect_clear.as_mut() {
+            pc.commit(placement);
+        }
+        match planned {
+            Some((piece, Source::Opener(sequence))) if &piece == placement => self.opener.advance(&sequence),
+            // anything else leaves the book behind
+            _ if self.opener.status == OpenerStatus::Active => self.opener.status = OpenerStatus::Invalid,
+            _ => (),
+        }
+        self.advance(placement);
+    }
+
+    fn advance(&mut self, placement: &Piece) {
+        self.stop_thinking();
+        if let Some(tree) = self.tree.as_mut() {
+            if !tree.advance(placement) {
+                self.tree = None;
+            }
+        }
+    }
+
+    fn take_tree(&mut self) -> SearchTree {
+        // the old tree is only any use if it was searched from this game
+        self.stop_thinking();
+        if let Some(mut tree) = self.tree.take() {
+            if tree.rebase(&self.game) {
+                return tree;
+            }
+        }
+        SearchTree::new(&self.game, &self.weight)
+    }
+
     // move gen
     fn command_list_string(commands: &CommandList) -> Vec<String> {
         commands
@@ -127,16 +423,11 @@ impl Bot {
     }
 
     pub fn moves_to_placement (
-        game: &mut Game,
+        game: &Game,
         piece: &Piece,
+        finesse: &Finesse,
     ) -> Result<CommandList, usize> {
-        let (moves, placements, _) = Bot::move_placement_score_1d(game, &Weights::default());
-        for (m, p) in zip(moves, placements) {
-            if &p == piece {
-                return Ok(m);
-            }
-        }
-        Err(0) // shitty error, make this better
+        finesse::finesse(game, piece, finesse).ok_or(0) // shitty error, make this better
     }
 
     pub fn moves_to_placements (
@@ -157,97 +448,43 @@ impl Bot {
     pub fn do_opener(&mut self) -> Result<CommandList, usize> {
         let mut sequence = vec![self.get_game().active_piece.piece_type];
         sequence.append(&mut self.get_game().piece_queue.get_vec());
-        let mut placement = self.opener.next_placement(&sequence);
+        let mut placement = self.opener.next_placement();
         placement.moved(PointVector(0 - self.get_game().game_data.lines_cleared as i8, 0));
-        Bot::moves_to_placement(&mut self.get_game().clone(), &placement)
+        // book placements don't say how they're reached, take a plain drop or else a spin
+        let game = self.get_game();
+        let moves = Bot::moves_to_placement(game, &Piece { last_kick: 999, ..placement }, &self.finesse)
+            .or_else(|_| Bot::moves_to_placement(game, &Piece { last_kick: 0, ..placement }, &self.finesse))?;
+        self.planned = Some((placement, Source::Opener(sequence)));
+        Ok(moves)
     }
 
     pub fn move_placement_score(
         &mut self,
-        depth: usize,
+        search: &SearchConfig,
         weights: &Weights,
         ) -> (MoveList, Vec<PlacementList>, ScoreList) {
-            let mut dummy = self.game.clone();
-            let (mut curr_moves, temp_placements, mut curr_scores) =
-                Bot::move_placement_score_1d(&mut dummy, weights);
-
-            let mut curr_placements: Vec<PlacementList> = temp_placements.into_iter().map(|x| vec!(x)).collect();
-
-            if depth <= 1 {
-                return (curr_moves, curr_placements, curr_scores);
-            }
-
-            let mut next_moves = MoveList::new();
-            let mut next_placements= Vec::new();
-            let mut next_scores = ScoreList::new();
-
-            //pruning parameters
-            let n = 50;
-            let prune_depth = 1;
-
-            for curr_depth in 1..depth {
-                //pruning curr_mps
-                if (curr_depth) % prune_depth == 0 {
-                    //TODO! use multiple scoring functions
-                    let combined_scores: Vec<Score> = curr_scores.clone().into_iter().map(|(versus, board)| versus + board).collect();
-
-                    (curr_moves, curr_placements) =
-                        zip(curr_moves, curr_placements)
-                            .enumerate()
-                            .sorted_by(|&(i1, _), &(i2, _)| combined_scores[i1].partial_cmp(&combined_scores[i2]).unwrap())
-                            .take(n)
-                            .map(|(_, p)| p)
-                            .unzip();
-
-                    curr_scores.sort_by(|(v1, b1), (v2, b2)| (v1 + b1).partial_cmp(&(v2 + b2)).unwrap());
-                    curr_scores.truncate(n);
-                }
-
-                //generating next_mps
-                for (one_move, placements, (_, versus)) in izip!(curr_moves.clone(), curr_placements.clone(), curr_scores.clone()) {
-                    let mut dummy = dummy.clone();
-
-                    //set dummy/cloned game
-                    for p in &placements {
-                        if dummy.active_piece.piece_type == p.piece_type {
-                            dummy.set_active_piece(p.clone());
-                            dummy.set_piece();
-                        }
-                        // need to hold
-                        else {
-                            dummy.hold();
-                            assert_eq!(dummy.active_piece.piece_type, p.piece_type);
-                            dummy.set_active_piece(p.clone());
-                            dummy.set_piece();
-                        }
-                    }
-
-                    let (_, add_placements, add_scores) =
-                        Bot::move_placement_score_1d(&mut dummy, weights);
-
-                    for (add_place, (board, add_versus)) in zip(add_placements, add_scores) {
-                        let mut placements = placements.clone();
-                        placements.push(add_place);
-
-                        next_moves.push(one_move.clone());
-                        next_placements.push(placements.clone());
-                        next_scores.push((board, (versus + add_versus) * (1.0-(0.5*curr_depth as f32/depth as f32))));
-                    }
-                }
-                curr_moves = mem::take(&mut next_moves);
-                curr_placements = mem::take(&mut next_placements);
-                curr_scores = mem::take(&mut next_scores);
-            }
-            (curr_moves, curr_placements, curr_scores)
+            // a search from scratch, get_next_move keeps its tree between moves instead
+            let pool = self.pool(search.threads);
+            let mut tree = SearchTree::new(&self.game, weights);
+            tree.grow(search, weights, &Limits::new(search.budget, None), pool.as_deref());
+            tree.into_frontier()
         }
 
     pub fn move_placement_score_1d(
         game: &mut Game,
         weight: &Weights,
     ) -> (MoveList, PlacementList, ScoreList) {
-        let (mut moves, mut placements, mut scores) =
-            Bot::trivial(game, false, weight);
-        Bot::non_trivial(game, weight, &mut moves, &mut placements, &mut scores);
+        Bot::move_placement_score_cached(game, weight, &ScoreCache::new(), &mut ScoreCache::new())
+    }
+
+    // boards in known or fresh aren't scored again, boards scored for the first time go in fresh
+    pub fn move_placement_score_cached(
+        game: &mut Game,
+        weight: &Weights,
+        known: &ScoreCache,
+        fresh: &mut ScoreCache,
+    ) -> (MoveList, PlacementList, ScoreList) {
+        let (mut moves, mut placements, mut scores) = Bot::search(game, false, weight, known, fresh);
 
         let hold_piece = game.get_hold_piece_or_next();
 
@@ -257,15 +494,7 @@ impl Bot {
 
         let active_piece = game.get_active_piece().get_type();
         game.set_active_piece(hold_piece);
-        let (mut hold_moves, mut hold_placements, mut hold_scores) =
-            Bot::trivial(game, true, weight);
-        Bot::non_trivial(
-            game,
-            weight,
-            &mut hold_moves,
-            &mut hold_placements,
-            &mut hold_scores,
-        );
+        let (hold_moves, hold_placements, hold_scores) = Bot::search(game, true, weight, known, fresh);
 
         moves.extend(hold_moves);
         placements.extend(hold_placements);
@@ -274,204 +503,147 @@ impl Bot {
         (moves, placements, scores)
     }
 
-    fn non_trivial(
-        game: &mut Game,
-        weight: &Weights,
-        moves: &mut MoveList,
-        placements: &mut PlacementList,
-        scores: &mut ScoreList,
-    ) {
-        let max_index = moves.len();
-        let mut index = 0;
-        while index < max_index {
-            Bot::non_trivial_recurse(
-                game,
-                weight,
-                &mut moves[index].clone(),
-                moves,
-                placements,
-                scores,
-                &placements[index].clone(),
-            );
-            index += 1;
-        }
-    }
-
-    fn non_trivial_recurse(
-        game: &mut Game,
-        weight: &Weights,
-        new_move: &mut CommandList,
-        moves: &mut MoveList,
-        placements: &mut PlacementList,
-        scores: &mut ScoreList,
-        start: &Piece,
-    ) {
-        for (command, action) in zip(COMMANDS, ACTIONS) {
-            game.set_active_piece(start.clone());
-
-            if !action(game) {
-                continue;
-            }
-
-            let sd = game.active_drop();
-            if !Bot::new_placement(&game.get_active_piece(), &placements) {
-                continue;
-            }
-            new_move.push(command);
-            if sd { new_move.push(Command::SoftDrop); }
-            moves.push(new_move.clone());
-            placements.push(game.clone().active_piece);
-            scores.push(Bot::score_game(game.clone(), weight, &game.active_piece));
-            Bot::non_trivial_recurse(
-                game,
-                weight,
-                new_move,
-                moves,
-                placements,
-                scores,
-                &game.get_active_piece().clone(),
-            );
-        }
-    }
-
-    fn trivial(
+    fn search(
         game: &mut Game,
         hold: bool,
         weight: &Weights,
+        known: &ScoreCache,
+        fresh: &mut ScoreCache,
     ) -> (MoveList, PlacementList, ScoreList) {
-        let mut moves = Vec::with_capacity(40);
-        let mut placements = Vec::with_capacity(40);
-        let mut scores = Vec::with_capacity(40);
-
-        for direction in 0..NUM_ROTATE_STATES {
-            if !game.active_piece_rotate_direction(direction) {
-                continue;
-            }
-
-            let mut base_move = Vec::with_capacity(8);
-            if hold {
-                base_move.push(Command::Hold);
+        let (mut moves, placements) = Bot::find_placements(game);
+        if hold {
+            for m in moves.iter_mut() {
+                m.insert(0, Command::Hold);
             }
-            base_move.push(ROTATIONS[direction]);
-            Bot::clone_and_extend(
-                &mut moves,
-                &mut placements,
-                &mut scores,
-                base_move.clone(),
-                game,
-                weight,
-            );
-            Bot::trivial_extend_direction(
-                &mut moves,
-                &mut placements,
-                &mut scores,
-                base_move.clone(),
-                Command::MoveLeft,
-                game,
-                weight,
-            );
-            game.reset_active_piece();
-            game.active_piece_rotate_direction(direction);
-            Bot::trivial_extend_direction(
-                &mut moves,
-                &mut placements,
-                &mut scores,
-                base_move.clone(),
-                Command::MoveRight,
-                game,
-                weight,
-            );
-            game.reset_active_piece();
         }
-
+        let scores = placements
+            .iter()
+            .map(|placement| Bot::score_game(game.clone(), weight, placement, known, fresh))
+            .collect();
         (moves, placements, scores)
     }
 
-    fn clone_and_extend(
-        moves: &mut MoveList,
-        placements: &mut PlacementList,
-        scores: &mut ScoreList,
-        mut new_move: CommandList,
-        game: &mut Game,
-        weight: &Weights,
-    ) {
-        let piece = game.clone().ret_active_drop();
-        new_move.push(Command::SoftDrop);
-        moves.push(new_move);
-        scores.push(Bot::score_game(game.clone(), weight, &piece));
-        placements.push(piece);
-    }
-    fn trivial_extend_direction(
-        moves: &mut MoveList,
-        placements: &mut PlacementList,
-        scores: &mut ScoreList,
-        mut base_move: CommandList,
-        command: Command,
-        game: &mut Game,
-        weight: &Weights,
-    ) {
-        while do_command(game, command) {
-            base_move.push(command);
-            Bot::clone_and_extend(moves, placements, scores, base_move.clone(), game, weight);
+    // every placement the active or hold piece can reach, with the inputs for it
+    pub fn find_paths(game: &Game) -> (MoveList, PlacementList) {
+        let mut dummy = game.clone();
+        let (mut moves, mut placements) = Bot::find_placements(&mut dummy);
+
+        let hold_piece = dummy.get_hold_piece_or_next();
+        if hold_piece.get_type() != dummy.get_active_piece().get_type() {
+            dummy.set_active_piece(hold_piece);
+            let (hold_moves, hold_placements) = Bot::find_placements(&mut dummy);
+            moves.extend(hold_moves.into_iter().map(|m| [vec![Command::Hold], m].concat()));
+            placements.extend(hold_placements);
         }
+        (moves, placements)
     }
 
-    fn new_placement(placement: &Piece, used_placements: &Vec<Piece>) -> bool {
-        !used_placements.contains(placement)
+    // the cheapest inputs finesse knows for a placement, or else the first path the search finds
+    pub fn inputs_for(game: &Game, placement: &Piece, finesse: &Finesse) -> Option<CommandList> {
+        Bot::moves_to_placement(game, placement, finesse).ok().or_else(|| {
+            let (moves, placements) = Bot::find_paths(game);
+            zip(moves, placements).find(|(_, p)| p == placement).map(|(m, _)| m)
+        })
     }
 
-    // scoring
-    fn score_game(mut game: Game, weights: &Weights, piece: &Piece) -> (Score, Score) {
-        game.board.set_piece(piece);
-        game.active_piece = piece.clone();
-        game.update();
-
-        //TODO: put all the logic in nice places (scorer class?)
-
-        // PC (pseudo) PRUNING
-        if false && (game.board.get_mino_count() % 2 == 0) {
-            // pseudo pruning, gives a large penalty for unsolvable/hard to solve boards
-            let penalty = (100000.0, Bot::score_board(&game.board, weights));
+    // breadth first search over every (rotation, position, last kick) the active piece can reach,
+    // giving each distinct locked placement (and spin) with the shortest inputs that reach it
+    pub fn find_placements(game: &mut Game) -> (MoveList, PlacementList) {
+        let start = *game.get_active_piece();
+        let allow_180 = game.get_game_rules().allow_180;
+        let allow_hard_drop = game.get_game_rules().allow_hard_drop;
+
+        let mut moves = MoveList::new();
+        let mut placements = PlacementList::new();
+        if game.board.piece_collision(&start) {
+            return (moves, placements);
+        }
 
-            let target_height = 4 + (game.board.get_mino_count() % 4)/2;
+        // each state is the piece, the state it came from and the command in between
+        let mut states: Vec<(Piece, usize, Command)> = vec![(start, 0, Command::None)];
+        let mut visited = HashSet::from([Bot::state_key(&start)]);
+        let mut locked = HashSet::new();
 
-            // limits search to target height
-            if game.board.get_max_height() > target_height{
-                return penalty
+        let mut index = 0;
+        while index < states.len() {
+            let piece = states[index].0;
+
+            // lock from here, the final drop is part of the lock command
+            game.set_active_piece(piece);
+            let mut dropped = game.ret_active_drop();
+            let soft_drop = !allow_hard_drop && dropped.center != piece.center;
+            if soft_drop {
+                dropped.set_kick(999);
+            }
+            let spin = game.get_spin_type(&dropped) as usize;
+            let (rotation, row, col, _) = Bot::state_key(&dropped);
+            if locked.insert((rotation, row, col, spin)) {
+                let mut path = Bot::path_to(&states, index);
+                if soft_drop {
+                    path.push(Command::SoftDrop);
+                }
+                moves.push(path);
+                placements.push(dropped);
             }
 
-            let parity = game.board.get_parities();
+            for (command, action) in zip(COMMANDS, ACTIONS) {
+                if command == Command::Rotate180 && !allow_180 {
+                    continue;
+                }
+                game.set_active_piece(piece);
+                if action(game) && visited.insert(Bot::state_key(game.get_active_piece())) {
+                    states.push((*game.get_active_piece(), index, command));
+                }
+            }
+            index += 1;
+        }
 
-            // the number of pieces required to fill the board to target height
-            let pieces_to_pc = (10*target_height - game.board.get_mino_count())/4;
+        game.set_active_piece(start);
+        (moves, placements)
+    }
 
-            // TODO: use a set or take piece order into account
+    pub(crate) fn state_key(piece: &Piece) -> (RotationState, i8, i8, usize) {
+        // only whether the piece last rotated, and with the last kick, matters for spins
+        let kick = match piece.get_last_kick() {
+            999 => 0,
+            4 => 2,
+            _ => 1,
+        };
+        (piece.rotation_state, piece.center.0, piece.center.1, kick)
+    }
 
-            // a queue of all the pieces available before the bot reaches target height
-            let mut usable_queue = game.piece_queue.get_queue().clone();
-            usable_queue.truncate(pieces_to_pc);
-            usable_queue.push_back(game.active_piece.piece_type);
-            if game.hold_piece.is_some(){
-                usable_queue.push_back(game.hold_piece.unwrap());
-            }
+    pub(crate) fn path_to(states: &[(Piece, usize, Command)], mut index: usize) -> CommandList {
+        let mut path = CommandList::new();
+        while index != 0 {
+            let (_, parent, command) = states[index];
+            path.push(command);
+            index = parent;
+        }
+        path.reverse();
+        path
+    }
 
-            // Checkerboard parity, solvability depends on if there is a playable T
-            // TODO: Take line clears into account
-            if parity.0 == false && (!usable_queue.contains(&6) || game.board.get_min_height() >= 2){
-                return penalty
-            }
+    // scoring
+    fn score_game(
+        mut game: Game,
+        weights: &Weights,
+        piece: &Piece,
+        known: &ScoreCache,
+        fresh: &mut ScoreCache,
+    ) -> (Score, Score) {
+        game.board.set_piece(piece);
+        game.active_piece = *piece;
+        game.update();
 
-            // Col Parity, solvability depends on if there is a playable L, J, or T
-            // Not affected by line clears
-            if parity.1 == false && !(usable_queue.contains(&1) || usable_queue.contains(&5) || usable_queue.contains(&6) || !game.board.get_min_height() >= 2){
-                return penalty
-            }
-        }
+        //TODO: put all the logic in nice places (scorer class?)
 
-        return (
-            Bot::score_board(&game.board, weights),
-            Bot::score_versus(&game.game_data, weights),
-        )
+        // the board score only depends on the board, the versus score on how it was reached
+        let hash = game.board.state_hash();
+        let board = match known.get(&hash).or_else(|| fresh.get(&hash)) {
+            Some(&score) => score,
+            None => *fresh.entry(hash).or_insert_with(|| Bot::score_board(&game.board, weights)),
+        };
+        (board, Bot::score_versus(&game.game_data, weights))
     }
 
     fn score_board(board: &Board, weights: &Weights) -> Score {
@@ -485,7 +657,10 @@ impl Bot {
         // let spin = Game::get_t_spin_type(piece, board);
         let combo_score = weight.combo_weight.eval(game_data.combo as f32);
         let b2b = weight.b2b_weight.eval(game_data.b2b as f32);
-        let attack = weight.damage_weight.eval(game_data.last_sent as f32);
+        // a charged surge is attack in the bank, worth less than attack already sent,
+        // so breaking b2b pays off once the surge and the clear beat keeping the chain
+        let surge = weight.damage_weight.eval(game_data.surge as f32) * SURGE_VALUE;
+        let attack = weight.damage_weight.eval((game_data.last_sent + game_data.last_cancelled) as f32);
         let clear = weight.clear_weight.eval(game_data.last_cleared as f32);
         let pc = game_data.all_clear;
         let t_spin = game_data.t_spin;
@@ -499,7 +674,7 @@ impl Bot {
             extra -= 100.0
         }
 
-        combo_score + b2b + attack + clear + extra
+        combo_score + b2b + surge + attack + clear + extra
     }
 
     fn get_height_differences_score(board: &Board, weight: &Weights) -> f32 {
@@ -517,7 +692,14 @@ impl Bot {
     }
 
     pub(crate) fn get_t_slot_score(board: &Board, weight: &Weights) -> f32 {
-        weight.t_slot_weight.eval(board.t_slot() as f32)
+        // open tsd shapes, plus the lines the best slot would clear, less the harder it is to get into
+        let lines = board
+            .t_slots()
+            .iter()
+            .map(|slot| slot.lines as f32 * slot.kind.odds())
+            .max_by(f32::total_cmp)
+            .unwrap_or(0.0);
+        weight.t_slot_weight.eval(board.t_slot() as f32) + weight.t_slot_lines_weight.eval(lines)
     }
 
     fn get_height_score(board: &Board, weight: &Weights) -> f32 {
@@ -537,3 +719,225 @@ impl Bot {
         out
     }
 }
+
+#[cfg(test)]
+mod bot_tests {
+    use super::*;
+    use crate::constants::versus_constants::TSpinType;
+    use polynomial::Polynomial;
+
+    // plays the inputs for a placement and checks they end up there
+    fn replay(game: &Game, moves: &CommandList, placement: &Piece) -> bool {
+        let mut game = game.clone();
+        do_move_list(&mut game, moves.clone());
+        let piece = game.ret_active_drop();
+        &piece == placement && piece.get_last_kick() == placement.get_last_kick()
+    }
+
+    #[test]
+    fn test_search_budget() {
+        let mut bot = Bot::new(Game::new(Some(1)));
+        let weights = Weights::default();
+        let depth = |bot: &mut Bot, search: SearchConfig| {
+            let (moves, placements, _) = bot.move_placement_score(&search, &weights);
+            assert!(!moves.is_empty());
+            placements[0].len()
+        };
+
+        let shallow = SearchConfig {
+            max_depth: 3,
+            beam_width: 5,
+            ..Default::default()
+        };
+        assert_eq!(depth(&mut bot, shallow.clone()), 3);
+
+        // a best move is ready even with no budget at all
+        let no_time = SearchConfig {
+            budget: Budget::Time(Duration::ZERO),
+            ..shallow.clone()
+        };
+        assert_eq!(depth(&mut bot, no_time), 1);
+
+        // five nodes finish the second depth, the sixth would start the third
+        let nodes = SearchConfig {
+            budget: Budget::Nodes(5),
+            ..shallow
+        };
+        assert_eq!(depth(&mut bot, nodes), 2);
+    }
+
+    #[test]
+    fn test_threads() {
+        let search = SearchConfig {
+            max_depth: 3,
+            beam_width: 10,
+            ..Default::default()
+        };
+        let mut one = Bot::new(Game::new(Some(4)));
+        let mut four = Bot::new(Game::new(Some(4)));
+        let four_threads = SearchConfig {
+            threads: 4,
+            ..search.clone()
+        };
+
+        let (_, one_placements, one_scores) = one.move_placement_score(&search, &Weights::default());
+        let (_, four_placements, four_scores) = four.move_placement_score(&four_threads, &Weights::default());
+        assert_eq!(one_placements, four_placements);
+        assert_eq!(one_scores, four_scores);
+    }
+
+    #[test]
+    fn test_suggestion() {
+        let mut bot = Bot::new(Game::new(Some(3)));
+        bot.set_search_config(SearchConfig {
+            max_depth: 3,
+            beam_width: 5,
+            ..Default::default()
+        });
+        let before = bot.get_game().clone();
+        let suggestion = bot.make_suggest_move();
+        let candidates = &suggestion.candidates;
+        assert!(suggestion.info.starts_with("beam"));
+        assert!(!candidates.is_empty() && candidates.len() <= CANDIDATES);
+
+        // the first is the move made, each starts somewhere different and they're in order
+        assert_eq!(candidates[0].input_list, suggestion.input_list);
+        let played = bot.get_game().board.get_arr();
+        let mut replayed = before.clone();
+        play(&mut replayed, &bot.lines[0].placements[0]);
+        assert_eq!(replayed.board.get_arr(), played);
+        for (a, b) in candidates.iter().tuple_windows() {
+            assert_ne!(a.placement, b.placement);
+            assert!(a.board_score + a.versus_score <= b.board_score + b.versus_score);
+        }
+        assert!(candidates.iter().all(|c| c.follow_up.len() == 2 && c.planned_attack >= c.attack));
+
+        let mut other = Bot::new(before);
+        other.set_strategy(Strategy::MonteCarlo(MonteCarloConfig {
+            iterations: 4,
+            depth: 2,
+            ..Default::default()
+        }));
+        let suggestion = other.make_suggest_move();
+        assert!(suggestion.info.starts_with("monte carlo"));
+        assert!(suggestion.candidates.is_empty());
+    }
+
+    #[test]
+    fn test_find_placements() {
+        let mut game = Game::new(Some(1));
+        game.set_active_piece(Piece::new(6));
+        let (moves, placements) = Bot::find_placements(&mut game);
+
+        // 4 rotations of a T on an empty board: 8 + 9 + 8 + 9 columns
+        assert_eq!(placements.len(), 34);
+        for (m, p) in zip(&moves, &placements) {
+            assert!(replay(&game, m, p));
+        }
+    }
+
+    #[test]
+    fn test_tuck() {
+        // an O has to drop and slide under the overhang on the left
+        let mut game = Game::new(Some(1));
+        game.board.add_list(vec![Point(2, 0), Point(2, 1), Point(2, 2)]);
+        game.set_active_piece(Piece::new(2));
+        let (moves, placements) = Bot::find_placements(&mut game);
+
+        let target = Piece {
+            center: Point(0, 0),
+            ..Piece::new(2)
+        };
+        let index = placements.iter().position(|p| p == &target).unwrap();
+        assert!(moves[index].contains(&Command::SoftDrop));
+        assert!(replay(&game, &moves[index], &placements[index]));
+    }
+
+    #[test]
+    fn test_t_slot_score() {
+        // the same tsd is worth less once both sides are roofed over
+        let mut open = Board::new();
+        open.set_row(0, vec![true, true, true, true, false, true, true, true, true, true]);
+        open.set_row(1, vec![true, true, true, false, false, false, true, true, true, true]);
+        open.add(2, 3);
+        let mut cross = open.clone();
+        cross.add(2, 5);
+
+        let weights = Weights {
+            t_slot_weight: Polynomial::new(vec![0.0]),
+            ..Default::default()
+        };
+        assert_eq!(Bot::get_t_slot_score(&open, &weights), -80.0);
+        assert_eq!(Bot::get_t_slot_score(&cross, &weights), -40.0);
+    }
+
+    #[test]
+    fn test_spin() {
+        // a t spin double under an overhang, the T has to drop before rotating in
+        let mut game = Game::new(Some(1));
+        for col in 0..BOARD_WIDTH {
+            if col != 4 {
+                game.board.add(0, col);
+            }
+            if !(3..=5).contains(&col) {
+                game.board.add(1, col);
+            }
+            if col <= 3 {
+                game.board.add(2, col);
+            }
+        }
+        game.set_active_piece(Piece::new(6));
+        let (moves, placements) = Bot::find_placements(&mut game);
+
+        let index = placements
+            .iter()
+            .enumerate()
+            .position(|(i, p)| {
+                p.rotation_state == 2
+                    && p.center == Point(1, 4)
+                    && Game::get_t_spin_type(&placements[i], &game.board) == TSpinType::Full
+            })
+            .unwrap();
+        assert!(replay(&game, &moves[index], &placements[index]));
+
+        game.set_active_piece(placements[index]);
+        game.set_piece();
+        assert_eq!(game.game_data.lines_cleared, 2);
+    }
+
+    #[test]
+    fn test_opener() {
+        // book placements carry no kick, finesse still has to find a way to every one of them
+        // in a stupid room any piece locked by a rotation where it can't move spins
+        let rules = GameRules {
+            spin_bonus: SpinBonus::Stupid,
+            ..Default::default()
+        };
+        let mut played = 0;
+        for seed in 0..40 {
+            let mut bot = Bot::new(Game::from_rules(Some(seed), rules.clone()));
+            let mut sequence = vec![bot.game.active_piece.piece_type];
+            sequence.append(&mut bot.game.piece_queue.get_vec());
+            bot.opener = openers::ndt();
+            bot.opener.init(&sequence);
+            if bot.opener.status == OpenerStatus::Invalid {
+                continue;
+            }
+            played += 1;
+            for _ in 0..7 {
+                let moves = bot.get_next_move();
+                assert_eq!(bot.info, "opener", "seed {}", seed);
+                // nothing in the first bag is a spin, so none of it should be played as one
+                let mut game = bot.game.clone();
+                do_move_list(&mut game, moves[..moves.len() - 1].to_vec());
+                let locked = game.ret_active_drop();
+                assert_eq!(game.get_spin_type(&locked), TSpinType::None, "seed {}", seed);
+                do_move_list(bot.get_game_mut(), moves);
+                bot.move_played();
+            }
+            assert!(bot.opener.bag_number() == 1 || bot.opener.status == OpenerStatus::Invalid);
+        }
+        assert!(played > 0);
+    }
+}
+
@@ -4,205 +4,641 @@ use crate::bot::*;
 use crate::game::Game;
 use crate::players::*;
 use crate::queue::piece_type_to_string;
+use crate::weight::Weights;
 
 use crate::constants::types::PieceType;
 use crate::game::game_rules_and_data::GameRules;
-use crate::Piece;
+use crate::constants::board_constants::{BOARD_HEIGHT, BOARD_WIDTH};
+use crate::protocol::*;
+use crate::versus::DamageModel;
 use futures_util::{SinkExt, StreamExt};
 use log::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
-use serde_json;
-use serde_json::json;
 use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::net::SocketAddr;
-use std::{thread, time};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinSet;
 use tokio_tungstenite::{accept_async, tungstenite::Error};
 use tungstenite::{Message, Result};
 
-#[derive(Serialize, Deserialize)]
-pub struct Suggestion {
-    pub input_list: Vec<String>,
-    pub info: String,
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerError {
+    // not json, or not a message we know
+    Malformed(String),
+    // well formed, but not something the bot can play
+    Invalid(String),
+    NotText,
+    // anything but a hello before the handshake
+    HandshakeRequired,
+    VersionMismatch(u32),
+    // max_sessions are already open
+    ServerFull,
 }
 
-impl Suggestion {
-    pub fn new(input_list: Vec<String>, info: String) -> Self {
-        Self { input_list, info }
+impl ServerError {
+    fn reason(&self) -> &'static str {
+        match self {
+            ServerError::Malformed(_) => "malformed",
+            ServerError::Invalid(_) => "invalid",
+            ServerError::NotText => "not_text",
+            ServerError::HandshakeRequired => "handshake_required",
+            ServerError::VersionMismatch(_) => "version_mismatch",
+            ServerError::ServerFull => "server_full",
+        }
     }
 }
 
-async fn accept_connection(peer: SocketAddr, stream: TcpStream) {
-    if let Err(e) = handle_connection(peer, stream).await {
-        match e {
-            Error::ConnectionClosed | Error::Protocol(_) | Error::Utf8 => (),
-            err => error!("Error processing connection: {:?}", err),
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Malformed(message) | ServerError::Invalid(message) => write!(f, "{}: {}", self.reason(), message),
+            ServerError::NotText => write!(f, "{}: expected a text frame", self.reason()),
+            ServerError::HandshakeRequired => write!(f, "{}: send a hello first", self.reason()),
+            ServerError::VersionMismatch(version) => write!(
+                f,
+                "{}: the server speaks version {}, the client {}",
+                self.reason(),
+                PROTOCOL_VERSION,
+                version
+            ),
+            ServerError::ServerFull => write!(f, "{}: too many sessions, try again later", self.reason()),
         }
     }
 }
 
-async fn handle_connection(peer: SocketAddr, stream: TcpStream) -> Result<()> {
-    let ws_stream = accept_async(stream).await.expect("Failed to accept");
-    info!("New WebSocket connection: {}", peer);
-    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-    // let interval = tokio::time::interval(Duration::from_millis(1000));
+impl From<&ServerError> for ServerMessage {
+    fn from(error: &ServerError) -> Self {
+        ServerMessage::Error {
+            reason: error.reason().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
 
-    // Echo incoming WebSocket messages and send a message periodically every second.
-    let mut bot = Bot::default();
-    bot.get_game_mut().hard_drop();
+impl RulesMessage {
+    fn to_rules(&self) -> std::result::Result<GameRules, ServerError> {
+        if self.boardheight == 0 || self.boardheight > BOARD_HEIGHT {
+            return Err(ServerError::Invalid(format!("board height {} is out of range", self.boardheight)));
+        }
+        let multiplier = self.garbagemultiplier.unwrap_or(1.0);
+        if !multiplier.is_finite() || multiplier < 0.0 {
+            return Err(ServerError::Invalid(format!("garbage multiplier {} is out of range", multiplier)));
+        }
 
-    loop {
-        tokio::select! {
-            Some(msg) = ws_receiver.next() => {
-                let msg = msg.unwrap();
+        // unknown names only warn, tetr.io adds options faster than we do
+        Ok(GameRules {
+            bag_type: self.bagtype.as_deref().unwrap_or("singleplayer").parse().unwrap_or_default(),
+            allow_hard_drop: self.allow_harddrop.unwrap_or(true),
+            allow_180: self.allow180,
+            allow_b2b_chain: self.b2bchaining.unwrap_or(true),
+            allow_b2b_charge: self.b2bcharging.unwrap_or(!self.b2bchaining.unwrap_or(true)),
+            max_board_height: self.boardheight,
+            kick_set: self.kickset.parse().unwrap_or_default(),
+            spin_bonus: self.spinbonuses.as_deref().unwrap_or("singleplayer").parse().unwrap_or_default(),
+            damage: DamageModel {
+                combo_table: self.combotable.as_deref().unwrap_or("multiplier").parse().unwrap_or_default(),
+                garbage_multiplier: multiplier,
+                garbage_increase: self.garbageincrease.unwrap_or(0.0),
+                margin_time: self.garbagemargin.unwrap_or(0.0) / 60.0,
+                all_clear_bonus: self.allclear_garbage.unwrap_or(10),
+            },
+        })
+    }
+}
 
-                if msg.is_close() {
-                    break;
-                }
+// one client's game, kept apart from the socket so bad input can be tested without one
+pub struct Session {
+    // for telling sessions apart in the logs
+    id: usize,
+    weights: Weights,
+    bot: Bot,
+    // what the client said it can do, None until the handshake
+    client_capabilities: Option<Vec<String>>,
+    suggestions: usize,
+    // when the game started, the clock the garbage multiplier runs on
+    started: Instant,
+}
 
-                let parsed: serde_json::Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
-                let parsed_type = parsed["type"].as_str().unwrap();
-
-                match parsed_type {
-                    "rules" => {
-                        eprintln!("start game");
-                        bot = create_bot_from_parsed(&parsed)
-                    },
-                    "play" => {
-
-                        // Set bot board to tetrio board
-                        for (r_index, row) in parsed["board"].as_array().unwrap().iter().rev().enumerate() {
-                            bot.get_game_mut().board.set_row(r_index, row.as_array().unwrap().iter().map(|col| col.as_bool().unwrap()).collect())
-                        }
-
-                        // Error Correction
-                        let tetrio_piece = str_to_piece_type(parsed["current"].as_str().unwrap());
-                        let bot_piece = bot.get_game().active_piece.piece_type;
-                        if tetrio_piece != bot_piece {
-                            eprintln!(
-                                "Active Piece Desynched: expected {}, but recieved {} instead",
-                                piece_to_string(Some(bot_piece)),
-                                piece_to_string(Some(tetrio_piece)));
-                        }
-
-                        let mut tetrio_queue: VecDeque<PieceType> = VecDeque::new();
-                        let bot_queue = bot.get_game().piece_queue.get_queue();
-
-                        for piece in parsed["queue"].as_array().unwrap(){
-                            tetrio_queue.push_back(str_to_piece_type(piece.as_str().unwrap()))
-                        }
-
-                        if &tetrio_queue != bot_queue {
-                            eprintln!("Mismatched Queue: expected {}, but received {} instead", bot.get_game().piece_queue, parsed["queue"]);
-                            bot.get_game_mut().piece_queue.set_queue(tetrio_queue);
-                        }
-
-                        let bot_hold = piece_to_string(bot.get_game().get_hold_piece());
-                        let tetrio_hold = parsed["hold"].as_str().unwrap_or_else(|| "*");
-                        if bot_hold != tetrio_hold{
-                             eprintln!("Mismatched Hold: expected {} as hold, received {} instead", bot_hold
-                            , tetrio_hold);
-                            bot.get_game_mut().hold_piece = str_to_piece(tetrio_hold);
-                        }
-
-                        // Primitive speedcap
-                        thread::sleep(time::Duration::from_millis(0));
-                        // Calculate and send move
-                        ws_sender.send(Message::Text(serde_json::to_string(&json!(bot.make_suggest_move())).unwrap())).await?;
-                    },
-                    "stop" => eprintln!("stop game"),
-                    "start" => {
-                        ws_sender.send(Message::Text(serde_json::to_string(&json!(bot.make_suggest_move())).unwrap())).await?
-                    },
-                    other => eprintln!("unexpected packet of type {}", other),
-                }
+impl Session {
+    pub fn new(id: usize, weights: Weights) -> Self {
+        let mut bot = server_bot(Game::new(None), &weights);
+        bot.get_game_mut().hard_drop();
+        Self {
+            id,
+            weights,
+            bot,
+            client_capabilities: None,
+            suggestions: 0,
+            started: Instant::now(),
+        }
+    }
 
-                fn str_to_piece(piece: &str) -> Option<usize> {
-                    match piece {
-                                "z" => Some(0),
-                                "l" => Some(1),
-                                "o" => Some(2),
-                                "s" => Some(3),
-                                "i" => Some(4),
-                                "j" => Some(5),
-                                "t" => Some(6),
-                                _ => None,
-                            }
-                }
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
 
-                fn str_to_piece_type(piece: &str) -> PieceType {
-                    match piece {
-                                "z" => 0,
-                                "l" => 1,
-                                "o" => 2,
-                                "s" => 3,
-                                "i" => 4,
-                                "j" => 5,
-                                "t" => 6,
-                                _ => panic!(),
-                            }
-                }
+    pub fn get_bot(&self) -> &Bot {
+        &self.bot
+    }
+
+    pub fn get_client_capabilities(&self) -> Option<&Vec<String>> {
+        self.client_capabilities.as_ref()
+    }
+
+    // the reply to send back, if the message wants one
+    pub fn handle(&mut self, text: &str) -> std::result::Result<Option<ServerMessage>, ServerError> {
+        let message: ClientMessage = serde_json::from_str(text).map_err(|e| ServerError::Malformed(e.to_string()))?;
 
-                fn piece_to_string(piece: Option<usize>) -> &'static str {
-                    match piece {
-                                Some(0) => "z",
-                                Some(1) => "l",
-                                Some(2) => "o",
-                                Some(3) => "s",
-                                Some(4) => "i",
-                                Some(5) => "j",
-                                Some(6) => "t",
-                                None => "*",
-                                Some(_) => "?"
-                            }
+        match message {
+            ClientMessage::Hello { version, capabilities } => {
+                if version != PROTOCOL_VERSION {
+                    return Err(ServerError::VersionMismatch(version));
                 }
+                self.client_capabilities = Some(capabilities);
+                Ok(Some(ServerMessage::hello()))
+            }
+            _ if self.client_capabilities.is_none() => Err(ServerError::HandshakeRequired),
+            ClientMessage::Rules(rules) => {
+                info!("[session {}] start game", self.id);
+                let seed = rules.seed as usize;
+                let rules = rules.to_rules()?;
+                self.bot.stop_thinking();
+                self.bot = server_bot(Game::from_rules(Some(seed), rules), &self.weights);
+                self.started = Instant::now();
+                Ok(None)
+            }
+            ClientMessage::State(state) => {
+                self.sync(state)?;
+                Ok(Some(self.suggest()))
+            }
+            ClientMessage::Stop => {
+                info!("[session {}] stop game", self.id);
+                self.bot.stop_thinking();
+                Ok(None)
+            }
+            ClientMessage::Start => Ok(Some(self.suggest())),
+            ClientMessage::Stats => Ok(Some(ServerMessage::Stats(self.stats()))),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        let data = &self.bot.get_game().game_data;
+        Stats {
+            pieces_placed: data.pieces_placed,
+            lines_cleared: data.lines_cleared,
+            lines_sent: data.lines_sent,
+            combo: data.combo,
+            b2b: data.b2b,
+            suggestions: self.suggestions,
+        }
+    }
+
+    fn suggest(&mut self) -> ServerMessage {
+        let suggestion = self.bot.make_suggest_move();
+        self.suggestions += 1;
+        // keep searching while the client plays the move out
+        self.bot.think_in_background();
+        ServerMessage::Suggestion(suggestion)
+    }
+
+    // set the bot's game to tetr.io's, checking everything before touching it
+    fn sync(&mut self, play: StateMessage) -> std::result::Result<(), ServerError> {
+        if play.board.len() > BOARD_HEIGHT {
+            return Err(ServerError::Invalid(format!("board has {} rows", play.board.len())));
+        }
+        if let Some(row) = play.board.iter().find(|row| row.len() != BOARD_WIDTH) {
+            return Err(ServerError::Invalid(format!("board row has {} columns", row.len())));
+        }
+
+        self.bot.stop_thinking();
+        let game = self.bot.get_game_mut();
+        game.game_data.seconds = self.started.elapsed().as_secs_f32();
+        for (r_index, row) in play.board.into_iter().rev().enumerate() {
+            game.board.set_row(r_index, row)
+        }
+
+        // Error Correction
+        let tetrio_piece = play.current.get_type();
+        let bot_piece = game.active_piece.piece_type;
+        if tetrio_piece != bot_piece {
+            warn!(
+                "[session {}] Active Piece Desynched: expected {}, but recieved {} instead",
+                self.id,
+                piece_type_to_string(bot_piece),
+                piece_type_to_string(tetrio_piece));
+        }
+
+        let tetrio_queue: VecDeque<PieceType> = play.queue.iter().map(|piece| piece.get_type()).collect();
+        if &tetrio_queue != game.piece_queue.get_queue() {
+            warn!("[session {}] Mismatched Queue: expected {}, but received {:?} instead", self.id, game.piece_queue, play.queue);
+            game.piece_queue.set_queue(tetrio_queue);
+        }
+
+        let tetrio_hold = play.hold.map(TetrioPiece::get_type);
+        if game.hold_piece != tetrio_hold {
+            warn!("[session {}] Mismatched Hold: expected {:?} as hold, received {:?} instead", self.id, game.hold_piece, tetrio_hold);
+            game.hold_piece = tetrio_hold;
+        }
+        Ok(())
+    }
+}
 
-                // eprintln!("packet of type {} was recieved!", parsed["type"]);
+// how long to wait after the listener fails to accept
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
 
-                // ws_sender.send(msg).await?; Echo response back to client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    // connections past this many are turned away
+    pub max_sessions: usize,
+    // seconds a session can go without a message before it's closed, 0 for never
+    pub idle_timeout: u64,
+    // only picked on the command line
+    #[serde(skip)]
+    pub frontend: Frontend,
+}
+
+// what the binary talks to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Frontend {
+    // this server, for the tetr.io client
+    #[default]
+    Tetrio,
+    // the tetris bot protocol over stdin and stdout
+    TbpStdio,
+    // the tetris bot protocol over websockets, listening on the address
+    TbpWebsocket(String),
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1".to_string(),
+            port: 23512,
+            max_sessions: 8,
+            idle_timeout: 300,
+            frontend: Frontend::Tetrio,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub const USAGE: &'static str = "usage: tetris [--config <file>] [--address <address>] [--port <port>] \
+        [--max-sessions <count>] [--idle-timeout <seconds>] [--tbp | --tbp-ws <address>] [weights...]";
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // --config, --address, --port, --max-sessions and --idle-timeout, in order so later ones win,
+    // and --tbp or --tbp-ws <address> to speak the tetris bot protocol instead;
+    // whatever isn't a server option is handed back
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> io::Result<(Self, Vec<String>)> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut config = Self::default();
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--tbp" {
+                config.frontend = Frontend::TbpStdio;
+                continue;
+            }
+            if !matches!(arg.as_str(), "--config" | "--address" | "--port" | "--max-sessions" | "--idle-timeout" | "--tbp-ws") {
+                rest.push(arg);
+                continue;
+            }
+            let value = args.next().ok_or_else(|| invalid(format!("{} needs a value", arg)))?;
+            let bad_value = |_| invalid(format!("bad value for {}: {}", arg, value));
+            match arg.as_str() {
+                "--config" => config = Self::load(Path::new(&value))?,
+                "--address" => config.address = value.clone(),
+                "--port" => config.port = value.parse().map_err(bad_value)?,
+                "--max-sessions" => config.max_sessions = value.parse().map_err(bad_value)?,
+                "--tbp-ws" => config.frontend = Frontend::TbpWebsocket(value.clone()),
+                _ => config.idle_timeout = value.parse().map_err(bad_value)?,
             }
         }
+        Ok((config, rest))
     }
-    Ok(())
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.address, self.port)
+    }
+
+    fn idle(&self) -> Duration {
+        if self.idle_timeout == 0 {
+            Duration::MAX
+        } else {
+            Duration::from_secs(self.idle_timeout)
+        }
+    }
+}
+
+async fn accept_connection(id: usize, peer: SocketAddr, stream: TcpStream, weights: Weights, idle: Duration, shutdown: watch::Receiver<bool>) {
+    if let Err(e) = handle_connection(id, peer, stream, weights, idle, shutdown).await {
+        match e {
+            Error::ConnectionClosed | Error::Protocol(_) | Error::Utf8 => (),
+            err => error!("[session {}] Error processing connection: {:?}", id, err),
+        }
+    }
+    info!("[session {}] closed", id);
+}
+
+async fn handle_connection(
+    id: usize,
+    peer: SocketAddr,
+    stream: TcpStream,
+    weights: Weights,
+    idle: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let ws_stream = accept_async(stream).await?;
+    info!("[session {}] new connection from {}", id, peer);
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+
+    let mut session = Session::new(id, weights);
+    loop {
+        // a message is handled to the end before shutdown or the timeout get a look in
+        let msg = tokio::select! {
+            msg = ws_receiver.next() => match msg {
+                Some(msg) => msg?,
+                None => break,
+            },
+            _ = tokio::time::sleep(idle) => {
+                info!("[session {}] idle for {:?}, closing", id, idle);
+                break;
+            }
+            _ = shutdown.changed() => break,
+        };
+        if msg.is_close() {
+            break;
+        }
+        // pings and pongs are answered by tungstenite
+        if !msg.is_text() && !msg.is_binary() {
+            continue;
+        }
+
+        let reply = if msg.is_text() {
+            let text = msg.to_text()?;
+            // the bot thinks synchronously, so keep it off the async workers
+            tokio::task::block_in_place(|| session.handle(text))
+        } else {
+            Err(ServerError::NotText)
+        };
+        let (packet, close) = match reply {
+            Ok(Some(message)) => (message, false),
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("[session {}] bad packet: {}", id, e);
+                // there's no talking to a client on another version
+                let close = matches!(e, ServerError::VersionMismatch(_));
+                (ServerMessage::from(&e), close)
+            }
+        };
+        ws_sender.send(Message::Text(packet_text(&packet))).await?;
+        if close {
+            break;
+        }
+    }
+    session.bot.stop_thinking();
+    ws_sender.close().await
+}
+
+// the error goes out before the connection closes, so the client knows why
+async fn refuse(id: usize, stream: TcpStream) -> Result<()> {
+    let mut ws_stream = accept_async(stream).await?;
+    let packet = ServerMessage::from(&ServerError::ServerFull);
+    ws_stream.send(Message::Text(packet_text(&packet))).await?;
+    warn!("[session {}] turned away, the server is full", id);
+    ws_stream.close(None).await
+}
+
+fn packet_text(packet: &ServerMessage) -> String {
+    serde_json::to_string(packet).expect("packets always serialize")
+}
+
+pub(crate) fn server_bot(game: Game, weights: &Weights) -> Bot {
+    let mut bot = Bot::from_weights(game, weights.clone());
+    if let Some(pool) = shared_pool() {
+        bot.set_pool(pool);
+    }
+    bot
 }
 
-fn create_bot_from_parsed(parsed: &serde_json::Value) -> Bot {
-    Bot::new(Game::from_rules(
-        Some(parsed["seed"].as_u64().unwrap() as usize),
-        GameRules {
-            bag_type: parsed["bagtype"]
-                .as_str()
-                .unwrap_or("singleplayer")
-                .parse()
-                .unwrap(),
-            allow_hard_drop: parsed["allow_harddrop"].as_bool().unwrap_or(true),
-            allow_180: parsed["allow180"].as_bool().unwrap(),
-            allow_b2b_chain: parsed["b2bchaining"].as_bool().unwrap_or(true),
-            max_board_height: parsed["boardheight"].as_u64().unwrap() as usize,
-            kick_set: parsed["kickset"].as_str().unwrap().parse().unwrap(),
-            spin_bonus: parsed["spinbonuses"]
-                .as_str()
-                .unwrap_or("singleplayer")
-                .parse()
-                .unwrap(),
-        },
-    ))
+// the server has the machine to itself, so every session thinks on one pool as wide as the cores
+// rather than each building its own
+fn shared_pool() -> Option<Arc<ThreadPool>> {
+    static POOL: OnceLock<Option<Arc<ThreadPool>>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        ThreadPoolBuilder::new().num_threads(threads).build().ok().map(Arc::new)
+    })
+    .clone()
 }
 
 #[tokio::main]
-pub async fn init() {
+pub async fn init(config: ServerConfig, weights: Weights) -> io::Result<()> {
     // env_logger::init();
 
-    let addr = "127.0.0.1:23512";
-    let listener = TcpListener::bind(&addr).await.expect("Can't listen");
+    let addr = config.bind_address();
+    let listener = TcpListener::bind(&addr).await?;
     info!("Listening on: {}", addr);
 
-    while let Ok((stream, _)) = listener.accept().await {
-        let peer = stream
-            .peer_addr()
-            .expect("connected streams should have a peer address");
-        info!("Peer address: {}", peer);
+    let sessions = Arc::new(Semaphore::new(config.max_sessions));
+    let (stop, shutdown) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    let mut id = 0;
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                // out of file descriptors and the like, wait a little rather than spin on it
+                Err(e) => {
+                    error!("accept failed: {}", e);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            },
+            // sessions are reaped as they end rather than held until shutdown
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = &mut ctrl_c => break,
+        };
+        id += 1;
+
+        let Ok(permit) = sessions.clone().try_acquire_owned() else {
+            connections.spawn(async move {
+                let _ = refuse(id, stream).await;
+            });
+            continue;
+        };
+        let (weights, idle, shutdown) = (weights.clone(), config.idle(), shutdown.clone());
+        connections.spawn(async move {
+            accept_connection(id, peer, stream, weights, idle, shutdown).await;
+            drop(permit);
+        });
+    }
+
+    // stop taking connections and let every session finish the message it's on
+    info!("shutting down, waiting on {} sessions", config.max_sessions - sessions.available_permits());
+    drop(listener);
+    let _ = stop.send(true);
+    while connections.join_next().await.is_some() {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod communications_tests {
+    use super::*;
+
+    const RULES: &str = r#"{"type":"rules","seed":7,"allow180":true,"boardheight":20,"kickset":"SRS+","bagtype":"7-bag"}"#;
+
+    fn state(board: &str, current: &str, queue: &str) -> String {
+        format!(r#"{{"type":"state","board":{},"current":"{}","queue":{},"hold":null}}"#, board, current, queue)
+    }
+
+    fn empty_board(rows: usize, cols: usize) -> String {
+        serde_json::to_string(&vec![vec![false; cols]; rows]).unwrap()
+    }
+
+    fn greeted() -> Session {
+        let mut session = Session::new(0, Weights::default());
+        let hello = format!(r#"{{"type":"hello","version":{}}}"#, PROTOCOL_VERSION);
+        assert_eq!(session.handle(&hello), Ok(Some(ServerMessage::hello())));
+        session
+    }
+
+    #[test]
+    fn test_handshake() {
+        let mut session = Session::new(0, Weights::default());
+        assert_eq!(session.handle(RULES), Err(ServerError::HandshakeRequired));
+        assert_eq!(
+            session.handle(r#"{"type":"hello","version":0,"capabilities":["rules"]}"#),
+            Err(ServerError::VersionMismatch(0))
+        );
+        assert!(session.get_client_capabilities().is_none());
+
+        let mut session = greeted();
+        assert_eq!(session.get_client_capabilities(), Some(&Vec::new()));
+        assert_eq!(session.handle(RULES), Ok(None));
+        assert_eq!(session.handle(r#"{"type":"stats"}"#), Ok(Some(ServerMessage::Stats(Stats::default()))));
+
+        let ServerMessage::Error { reason, message } = ServerMessage::from(&ServerError::VersionMismatch(2)) else {
+            panic!("not an error");
+        };
+        assert_eq!(reason, "version_mismatch");
+        assert!(message.contains("client 2"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut session = greeted();
+        let bad = [
+            "not json",
+            r#"{"no":"type"}"#,
+            r#"{"type":"dance"}"#,
+            // no seed
+            r#"{"type":"rules","allow180":true,"boardheight":20,"kickset":"SRS+"}"#,
+            r#"{"type":"rules","seed":"seven","allow180":true,"boardheight":20,"kickset":"SRS+"}"#,
+            &state(&empty_board(20, 10), "q", r#"["i"]"#),
+            &state(&empty_board(20, 10), "t", r#"["i","x"]"#),
+        ];
+        for text in bad {
+            assert!(matches!(session.handle(text), Err(ServerError::Malformed(_))), "{}", text);
+        }
+
+        let invalid = [
+            r#"{"type":"rules","seed":7,"allow180":true,"boardheight":0,"kickset":"SRS+"}"#,
+            r#"{"type":"rules","seed":7,"allow180":true,"boardheight":20,"kickset":"SRS+","garbagemultiplier":-1}"#,
+            &state(&empty_board(20, 9), "t", r#"["i"]"#),
+            &state(&empty_board(41, 10), "t", r#"["i"]"#),
+        ];
+        for text in invalid {
+            assert!(matches!(session.handle(text), Err(ServerError::Invalid(_))), "{}", text);
+        }
+
+        let packet = serde_json::to_value(ServerMessage::from(&ServerError::NotText)).unwrap();
+        assert_eq!(packet["type"], "error");
+        assert_eq!(packet["reason"], "not_text");
+    }
+
+    #[test]
+    fn test_session_survives() {
+        let mut session = greeted();
+        assert!(session.handle(RULES).unwrap().is_none());
+        assert!(session.handle("{").is_err());
+
+        // a bad board leaves the game as it was
+        let mut board = vec![vec![false; 10]; 20];
+        board[19][0] = true;
+        let wide = serde_json::to_string(&vec![vec![true; 11]; 20]).unwrap();
+        assert!(session.handle(&state(&wide, "t", r#"["i"]"#)).is_err());
+        assert_eq!(session.get_bot().get_game().board.get_mino_count(), 0);
+
+        let board = serde_json::to_string(&board).unwrap();
+        let reply = session.handle(&state(&board, "t", r#"["i","o","s","z","l","j"]"#)).unwrap();
+        let Some(ServerMessage::Suggestion(suggestion)) = reply else {
+            panic!("no suggestion");
+        };
+        assert!(!suggestion.input_list.is_empty());
+        assert_eq!(suggestion.candidates[0].input_list, suggestion.input_list);
+        assert_eq!(session.get_bot().get_game().game_data.pieces_placed, 1);
+        assert_eq!(session.stats().suggestions, 1);
+    }
+
+    #[test]
+    fn test_shared_pool() {
+        // sessions share the cores instead of each taking all of them
+        let one = server_bot(Game::new(None), &Weights::default());
+        let two = server_bot(Game::new(None), &Weights::default());
+        match (one.get_pool(), two.get_pool()) {
+            (Some(a), Some(b)) => assert!(Arc::ptr_eq(a, b)),
+            (a, b) => assert!(a.is_none() && b.is_none()),
+        }
+    }
+
+    #[test]
+    fn test_server_config() {
+        let args = ["--port", "9000", "aggressive", "--max-sessions", "2", "--idle-timeout", "0"];
+        let (config, rest) = ServerConfig::from_args(args.iter().map(|a| a.to_string())).unwrap();
+        assert_eq!(rest, vec!["aggressive".to_string()]);
+        assert_eq!(config.bind_address(), "127.0.0.1:9000");
+        assert_eq!(config.max_sessions, 2);
+        assert_eq!(config.idle(), Duration::MAX);
+
+        assert_eq!(config.frontend, Frontend::Tetrio);
+        let (config, _) = ServerConfig::from_args(["--tbp-ws".to_string(), "127.0.0.1:9001".to_string()]).unwrap();
+        assert_eq!(config.frontend, Frontend::TbpWebsocket("127.0.0.1:9001".to_string()));
+        assert_eq!(ServerConfig::from_args(["--tbp".to_string()]).unwrap().0.frontend, Frontend::TbpStdio);
+        assert!(ServerConfig::from_args(["--port".to_string()]).is_err());
+        assert!(ServerConfig::from_args(["--port".to_string(), "lots".to_string()]).is_err());
+
+        // a file sets what it has, and flags after it still win
+        let path = std::env::temp_dir().join(format!("ivfish_server_config_test_{}.toml", std::process::id()));
+        fs::write(&path, "address = \"0.0.0.0\"\nport = 8000\n").unwrap();
+        let args = ["--config", path.to_str().unwrap(), "--port", "8001"];
+        let (config, _) = ServerConfig::from_args(args.iter().map(|a| a.to_string())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.bind_address(), "0.0.0.0:8001");
+        assert_eq!(config.idle_timeout, ServerConfig::default().idle_timeout);
 
-        tokio::spawn(accept_connection(peer, stream));
+        let ServerMessage::Error { reason, .. } = ServerMessage::from(&ServerError::ServerFull) else {
+            panic!("not an error");
+        };
+        assert_eq!(reason, "server_full");
     }
 }
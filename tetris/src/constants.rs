@@ -21,6 +21,8 @@ pub mod types {
     pub type Score = f32;
     pub type MoveList = Vec<CommandList>;
     pub type ScoreList = Vec<(Score, Score)>;
+    // board scores by board hash
+    pub type ScoreCache = std::collections::HashMap<u64, Score>;
     pub type PlacementList = Vec<Piece>;
     pub type BagPlacement = [Piece; 7];
     pub type BagPlacements = Vec<BagPlacement>;
@@ -70,6 +72,7 @@ pub mod piece_constants {
 
 pub mod versus_constants {
     #[derive(Debug, PartialEq, Clone, Copy)]
+    #[allow(clippy::upper_case_acronyms)]
     pub enum AttackType {
         None,
         S,
@@ -81,9 +84,15 @@ pub mod versus_constants {
         TS,
         TD,
         TT,
+        // spins by pieces other than t, for all spin rooms
+        SSM,
+        SDM,
+        SS,
+        SD,
+        ST,
     }
 
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, PartialEq, Clone, Copy)]
     pub enum TSpinType {
         None,
         Full,
@@ -100,16 +109,58 @@ pub mod queue_constants {
 
 }
 
+// keys for hashing game states. this isn't an incremental zobrist hash, line clears shift every
+// cell, so the whole board is hashed again each time, one mix per column
+pub mod hash_constants {
+    use super::board_constants::BOARD_WIDTH;
+    use super::piece_constants::NUM_PIECES;
+
+    // splitmix64, used both to make the keys and to spread whole columns over 64 bits
+    pub const fn mix(x: u64) -> u64 {
+        let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    const fn keys<const N: usize>(seed: u64) -> [u64; N] {
+        let mut out = [0; N];
+        let mut i = 0;
+        while i < N {
+            out[i] = mix(seed.wrapping_mul(1000).wrapping_add(i as u64));
+            i += 1;
+        }
+        out
+    }
+
+    pub const COLUMN_KEYS: [u64; BOARD_WIDTH] = keys(1);
+    pub const ACTIVE_KEYS: [u64; NUM_PIECES] = keys(2);
+    // the last key is an empty hold
+    pub const HOLD_KEYS: [u64; NUM_PIECES + 1] = keys(3);
+    pub const B2B_KEY: u64 = mix(4);
+    pub const COMBO_KEY: u64 = mix(5);
+    pub const QUEUE_KEY: u64 = mix(6);
+    pub const GARBAGE_KEY: u64 = mix(7);
+    pub const SURGE_KEY: u64 = mix(8);
+}
+
 pub mod bot_constants {
     use std::fmt::{Display, Formatter};
     use crate::game::Game;
 
+    // how much a charged surge counts for next to attack that's been sent
+    pub const SURGE_VALUE: f32 = 0.5;
+    // placements sent along with a suggestion
+    pub const CANDIDATES: usize = 5;
+
     #[derive(Copy, Clone, Debug, PartialEq)]
 
     pub enum Command {
         None,
         MoveLeft,
         MoveRight,
+        DasLeft,
+        DasRight,
         SoftDrop,
         RotateCW,
         RotateCCW,
@@ -124,6 +175,8 @@ pub mod bot_constants {
                 Command::None => write!(f, "None")?,
                 Command::MoveLeft => write!(f, "MoveLeft")?,
                 Command::MoveRight => write!(f, "MoveRight")?,
+                Command::DasLeft => write!(f, "DasLeft")?,
+                Command::DasRight => write!(f, "DasRight")?,
                 Command::SoftDrop => write!(f, "SoftDrop")?,
                 Command::RotateCW => write!(f, "RotateCW")?,
                 Command::RotateCCW => write!(f, "RotateCCW")?,
@@ -145,22 +198,30 @@ pub mod bot_constants {
 
     pub const DIRECTIONS: [Command; 2] = [Command::MoveRight, Command::MoveLeft];
 
-    pub const COMMANDS: [Command; 5] = [
+    pub const COMMANDS: [Command; 6] = [
         Command::MoveRight,
         Command::MoveLeft,
         Command::RotateCW,
         Command::RotateCCW,
         Command::Rotate180,
-        // Command::SoftDrop,
+        Command::SoftDrop,
     ];
 
-    pub const ACTIONS: [fn(&mut Game) -> bool; 5] = [
+    pub const ACTIONS: [fn(&mut Game) -> bool; 6] = [
         Game::active_right,
         Game::active_left,
         Game::active_cw,
         Game::active_ccw,
         Game::active_180,
-        // Game::active_drop,
+        Game::active_soft_drop,
+    ];
+
+    // das never reaches anything taps can't, so it's only used for finesse
+    pub const DAS_COMMANDS: [Command; 2] = [Command::DasRight, Command::DasLeft];
+
+    pub const DAS_ACTIONS: [fn(&mut Game) -> bool; 2] = [
+        Game::active_das_right,
+        Game::active_das_left,
     ];
 }
 
@@ -296,6 +357,150 @@ pub mod offset {
         [PointVector(0, -1), PointVector(1, -1), PointVector(1, 0)],
     ];
 
+    pub const SRS_FIVE_OFFSETS: [[[PointVector; 5]; 2]; NUM_ROTATE_STATES] = [
+        SRS_FIVE_OFFSET_ZERO,
+        SRS_FIVE_OFFSET_ONE,
+        SRS_FIVE_OFFSET_TWO,
+        SRS_FIVE_OFFSET_THREE,
+    ];
+
+    // the I piece rotates around the middle of its bounding box instead of a cell, so an I
+    // rotation always shifts the center by the difference between these (row, col) positions
+    pub const FIVE_CENTERS: [PointVector; NUM_ROTATE_STATES] = [
+        PointVector(2, 1),
+        PointVector(2, 2),
+        PointVector(1, 2),
+        PointVector(1, 1),
+    ];
+
+    // the tables below are relative to the piece, so the I piece still needs FIVE_CENTERS added
+
+    pub const SRS_X_180_OFFSETS: [[PointVector; 12]; NUM_ROTATE_STATES] = [
+        [
+            PointVector(0, 0),
+            PointVector(0, 1),
+            PointVector(0, 2),
+            PointVector(1, 1),
+            PointVector(1, 2),
+            PointVector(0, -1),
+            PointVector(0, -2),
+            PointVector(1, -1),
+            PointVector(1, -2),
+            PointVector(-1, 0),
+            PointVector(0, 3),
+            PointVector(0, -3),
+        ],
+        [
+            PointVector(0, 0),
+            PointVector(1, 0),
+            PointVector(2, 0),
+            PointVector(1, -1),
+            PointVector(2, -1),
+            PointVector(-1, 0),
+            PointVector(-2, 0),
+            PointVector(-1, -1),
+            PointVector(-2, -1),
+            PointVector(0, 1),
+            PointVector(3, 0),
+            PointVector(-3, 0),
+        ],
+        [
+            PointVector(0, 0),
+            PointVector(0, -1),
+            PointVector(0, -2),
+            PointVector(-1, -1),
+            PointVector(-1, -2),
+            PointVector(0, 1),
+            PointVector(0, 2),
+            PointVector(-1, 1),
+            PointVector(-1, 2),
+            PointVector(1, 0),
+            PointVector(0, -3),
+            PointVector(0, 3),
+        ],
+        [
+            PointVector(0, 0),
+            PointVector(1, 0),
+            PointVector(2, 0),
+            PointVector(1, 1),
+            PointVector(2, 1),
+            PointVector(-1, 0),
+            PointVector(-2, 0),
+            PointVector(-1, 1),
+            PointVector(-2, 1),
+            PointVector(0, -1),
+            PointVector(3, 0),
+            PointVector(-3, 0),
+        ],
+    ];
+
+    pub const TETRA_X_OFFSETS: [PointVector; 9] = [
+        PointVector(0, 0),
+        PointVector(-1, 0),
+        PointVector(0, -1),
+        PointVector(0, 1),
+        PointVector(-1, -1),
+        PointVector(-1, 1),
+        PointVector(1, 0),
+        PointVector(1, -1),
+        PointVector(1, 1),
+    ];
+
+    // clockwise kicks, mirrored horizontally for counterclockwise
+    pub const ASC_OFFSETS: [PointVector; 22] = [
+        PointVector(0, 0),
+        PointVector(0, -1),
+        PointVector(-1, 0),
+        PointVector(-1, -1),
+        PointVector(-2, 0),
+        PointVector(-2, -1),
+        PointVector(0, -2),
+        PointVector(-1, -2),
+        PointVector(-2, -2),
+        PointVector(0, 1),
+        PointVector(-1, 1),
+        PointVector(1, 0),
+        PointVector(1, -1),
+        PointVector(1, -2),
+        PointVector(-2, 1),
+        PointVector(0, 2),
+        PointVector(2, 0),
+        PointVector(2, -1),
+        PointVector(2, -2),
+        PointVector(-1, 2),
+        PointVector(-2, 2),
+        PointVector(1, 1),
+    ];
+
+    // no kick, right, left
+    pub const ARS_OFFSETS: [PointVector; 3] = [
+        PointVector(0, 0),
+        PointVector(0, 1),
+        PointVector(0, -1),
+    ];
+
+    // NRS and ARS pieces sit in different spots of their bounding box than SRS pieces,
+    // so each rotation state is shifted by these amounts (Z, L, O, S, I, J, T)
+    pub const NRS_STATE_OFFSETS: [[PointVector; NUM_ROTATE_STATES]; NUM_PIECES] = [
+        [PointVector(-1, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 1)],
+        [PointVector(0, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+        [PointVector(0, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+        [PointVector(-1, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 1)],
+        [PointVector(0, 0), PointVector(1, 0), PointVector(1, 0), PointVector(1, 1)],
+        [PointVector(0, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+        [PointVector(0, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+    ];
+
+    pub const ARS_STATE_OFFSETS: [[PointVector; NUM_ROTATE_STATES]; NUM_PIECES] = [
+        [PointVector(-1, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 1)],
+        [PointVector(-1, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+        [PointVector(0, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+        [PointVector(-1, 0), PointVector(0, -1), PointVector(0, 0), PointVector(0, 0)],
+        [PointVector(0, 0), PointVector(0, 0), PointVector(1, 0), PointVector(0, 1)],
+        [PointVector(-1, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+        [PointVector(-1, 0), PointVector(0, 0), PointVector(0, 0), PointVector(0, 0)],
+    ];
+
     const THREE_OFFSET_ZERO: [[PointVector; 5]; 2] = [
         [
             PointVector(0, 0),
@@ -431,4 +636,72 @@ pub mod offset {
             PointVector(-1, -1),
         ],
     ];
+
+    const SRS_FIVE_OFFSET_ZERO: [[PointVector; 5]; 2] = [
+        [
+            PointVector(0, 1),
+            PointVector(0, -1),
+            PointVector(0, 2),
+            PointVector(-1, -1),
+            PointVector(2, 2),
+        ],
+        [
+            PointVector(-1, 0),
+            PointVector(-1, -1),
+            PointVector(-1, 2),
+            PointVector(1, -1),
+            PointVector(-2, 2),
+        ],
+    ];
+
+    const SRS_FIVE_OFFSET_ONE: [[PointVector; 5]; 2] = [
+        [
+            PointVector(-1, 0),
+            PointVector(-1, -1),
+            PointVector(-1, 2),
+            PointVector(1, -1),
+            PointVector(-2, 2),
+        ],
+        [
+            PointVector(0, -1),
+            PointVector(0, 1),
+            PointVector(0, -2),
+            PointVector(1, 1),
+            PointVector(-2, -2),
+        ],
+    ];
+
+    const SRS_FIVE_OFFSET_TWO: [[PointVector; 5]; 2] = [
+        [
+            PointVector(0, -1),
+            PointVector(0, 1),
+            PointVector(0, -2),
+            PointVector(1, 1),
+            PointVector(-2, -2),
+        ],
+        [
+            PointVector(1, 0),
+            PointVector(1, 1),
+            PointVector(1, -2),
+            PointVector(-1, 1),
+            PointVector(2, -2),
+        ],
+    ];
+
+    const SRS_FIVE_OFFSET_THREE: [[PointVector; 5]; 2] = [
+        [
+            PointVector(1, 0),
+            PointVector(1, 1),
+            PointVector(1, -2),
+            PointVector(-1, 1),
+            PointVector(2, -2),
+        ],
+        [
+            PointVector(0, 1),
+            PointVector(0, -1),
+            PointVector(0, 2),
+            PointVector(-1, -1),
+            PointVector(2, 2),
+        ],
+    ];
 }
@@ -0,0 +1,226 @@
+#![allow(dead_code)]
+
+use crate::bot::Bot;
+use crate::constants::bot_constants::*;
+use crate::constants::types::CommandList;
+use crate::constants::versus_constants::TSpinType;
+use crate::game::Game;
+use crate::piece::Piece;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::iter::zip;
+
+// handling settings, in frames (sdf in rows per frame, 0 is instant)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Handling {
+    pub das: usize,
+    pub arr: usize,
+    pub sdf: usize,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self {
+            das: 10,
+            arr: 2,
+            sdf: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Finesse {
+    // fewest keypresses, ties broken by frames
+    Keys(Handling),
+    // fewest frames, ties broken by keypresses
+    Frames(Handling),
+}
+
+impl Default for Finesse {
+    fn default() -> Self {
+        Finesse::Keys(Handling::default())
+    }
+}
+
+impl Finesse {
+    fn handling(&self) -> &Handling {
+        match self {
+            Finesse::Keys(handling) | Finesse::Frames(handling) => handling,
+        }
+    }
+
+    fn cost(&self, command: Command, before: &Piece, after: &Piece) -> (usize, usize) {
+        let handling = self.handling();
+        let frames = match command {
+            Command::DasLeft | Command::DasRight => {
+                let distance = before.center.1.abs_diff(after.center.1) as usize;
+                handling.das + handling.arr * (distance - 1)
+            }
+            Command::SoftDrop if handling.sdf > 0 => {
+                let distance = before.center.0.abs_diff(after.center.0) as usize;
+                distance.div_ceil(handling.sdf)
+            }
+            _ => 1,
+        };
+        match self {
+            Finesse::Keys(_) => (1, frames),
+            Finesse::Frames(_) => (frames, 1),
+        }
+    }
+}
+
+// the cheapest inputs that lock the active (or held) piece as the target,
+// with the same spin if the target is one
+pub fn finesse(game: &Game, target: &Piece, finesse: &Finesse) -> Option<CommandList> {
+    let mut game = game.clone();
+    let mut prefix = CommandList::new();
+    let mut start_cost = (0, 0);
+    if game.get_active_piece().get_type() != target.get_type() {
+        let hold = game.get_hold_piece_or_next();
+        if hold.get_type() != target.get_type() {
+            return None;
+        }
+        game.set_active_piece(hold);
+        prefix.push(Command::Hold);
+        start_cost = finesse.cost(Command::Hold, &hold, &hold);
+    }
+
+    let allow_180 = game.get_game_rules().allow_180;
+    let allow_hard_drop = game.get_game_rules().allow_hard_drop;
+    let target_spin = game.get_spin_type(target);
+
+    // dijkstra over the same states as the placement search, costs are (primary, tie break)
+    let start = *game.get_active_piece();
+    let mut states: Vec<(Piece, usize, Command)> = vec![(start, 0, Command::None)];
+    let mut best = HashMap::from([(Bot::state_key(&start), start_cost)]);
+    let mut heap = BinaryHeap::from([Reverse((start_cost, 0))]);
+
+    while let Some(Reverse((cost, index))) = heap.pop() {
+        let piece = states[index].0;
+        if best.get(&Bot::state_key(&piece)).is_some_and(|&c| c < cost) {
+            continue;
+        }
+
+        // without hard drop the piece has to be soft dropped into place first
+        game.set_active_piece(piece);
+        let locked = if allow_hard_drop { game.ret_active_drop() } else { piece };
+        if (allow_hard_drop || !game.active_drop())
+            && &locked == target
+            && game.get_spin_type(&locked) == target_spin
+        {
+            let mut out = prefix;
+            out.extend(Bot::path_to(&states, index));
+            return Some(out);
+        }
+
+        for (command, action) in zip(COMMANDS, ACTIONS).chain(zip(DAS_COMMANDS, DAS_ACTIONS)) {
+            if command == Command::Rotate180 && !allow_180 {
+                continue;
+            }
+            game.set_active_piece(piece);
+            if !action(&mut game) {
+                continue;
+            }
+            let next = *game.get_active_piece();
+            let step = finesse.cost(command, &piece, &next);
+            let next_cost = (cost.0 + step.0, cost.1 + step.1);
+            let key = Bot::state_key(&next);
+            if best.get(&key).is_none_or(|&c| next_cost < c) {
+                best.insert(key, next_cost);
+                states.push((next, index, command));
+                heap.push(Reverse((next_cost, states.len() - 1)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod finesse_tests {
+    use super::*;
+    use crate::game::game_rules_and_data::GameRules;
+    use crate::players::do_move_list;
+    use crate::point_vector::Point;
+
+    fn target(piece_type: usize, rotation_state: usize, center: Point) -> Piece {
+        Piece {
+            rotation_state,
+            center,
+            ..Piece::new(piece_type)
+        }
+    }
+
+    fn game_with(piece_type: usize, rules: GameRules) -> Game {
+        let mut game = Game::from_rules(Some(1), rules);
+        game.set_active_piece(Piece::new(piece_type));
+        game
+    }
+
+    #[test]
+    fn test_das() {
+        let game = game_with(6, GameRules::default());
+        let keys = Finesse::default();
+
+        let left_wall = target(6, 0, Point(0, 1));
+        assert_eq!(finesse(&game, &left_wall, &keys), Some(vec![Command::DasLeft]));
+
+        let one_right = target(6, 0, Point(0, 5));
+        assert_eq!(finesse(&game, &one_right, &keys), Some(vec![Command::MoveRight]));
+
+        // two taps beat waiting out das
+        let frames = Finesse::Frames(Handling::default());
+        let two_left = target(6, 0, Point(0, 2));
+        assert_eq!(finesse(&game, &two_left, &keys).unwrap().len(), 2);
+        assert_eq!(
+            finesse(&game, &two_left, &frames),
+            Some(vec![Command::MoveLeft, Command::MoveLeft])
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let upside_down = target(6, 2, Point(1, 4));
+        let game = game_with(6, GameRules::default());
+        assert_eq!(finesse(&game, &upside_down, &Finesse::default()), Some(vec![Command::Rotate180]));
+
+        let rules = GameRules {
+            allow_180: false,
+            allow_hard_drop: false,
+            ..Default::default()
+        };
+        let mut game = game_with(6, rules);
+        let inputs = finesse(&game, &upside_down, &Finesse::default()).unwrap();
+        assert_eq!(inputs, vec![Command::RotateCW, Command::RotateCW, Command::SoftDrop]);
+        do_move_list(&mut game, inputs);
+        assert_eq!(game.get_active_piece(), &upside_down);
+    }
+
+    #[test]
+    fn test_spin() {
+        // a tsd under an overhang, the t can only get in by rotating, which always spins
+        let mut game = game_with(6, GameRules::default());
+        game.board.set_row(0, vec![true, true, true, true, false, true, true, true, true, true]);
+        game.board.set_row(1, vec![true, true, true, false, false, false, true, true, true, true]);
+        game.board.add(2, 3);
+        let slot = target(6, 2, Point(1, 4));
+        assert_eq!(game.get_spin_type(&slot), TSpinType::None);
+        assert_eq!(finesse(&game, &slot, &Finesse::default()), None);
+
+        let spun = Piece { last_kick: 0, ..slot };
+        assert_eq!(game.get_spin_type(&spun), TSpinType::Full);
+        let inputs = finesse(&game, &spun, &Finesse::default()).unwrap();
+        do_move_list(&mut game, inputs);
+        game.active_drop();
+        assert_eq!(game.get_spin_type(game.get_active_piece()), TSpinType::Full);
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut game = game_with(6, GameRules::default());
+        game.hold_piece = Some(4);
+
+        let flat_i = target(4, 0, Point(0, 4));
+        assert_eq!(finesse(&game, &flat_i, &Finesse::default()), Some(vec![Command::Hold]));
+        assert_eq!(finesse(&game, &target(0, 0, Point(0, 4)), &Finesse::default()), None);
+    }
+}
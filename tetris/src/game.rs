@@ -1,14 +1,17 @@
 #![allow(dead_code)]
 
 use crate::board::Board;
-use crate::constants::piece_constants::{NUM_ROTATE_STATES, RELATIVE_CORNERS};
+use crate::constants::bot_constants::Command;
+use crate::constants::piece_constants::{NUM_PIECES, NUM_ROTATE_STATES, RELATIVE_CORNERS};
+use crate::constants::hash_constants::*;
 use crate::constants::types::{PieceType, RotationDirection};
 use crate::constants::versus_constants::*;
 use crate::piece::Piece;
-use crate::point_vector::PointVector;
+use crate::point_vector::{Point, PointVector};
 use crate::queue::{piece_type_to_string, BagType, PieceQueue};
 use crate::versus::*;
 use game_rules_and_data::*;
+use std::collections::VecDeque;
 use std::fmt::{Display, Formatter};
 use crate::game::game_rules_and_data::SpinBonus::TSpin;
 
@@ -21,15 +24,17 @@ pub struct Game {
 
     pub active_piece: Piece,
     pub hold_piece: Option<PieceType>,
+
+    pub garbage_queue: VecDeque<Garbage>,
 }
 
 impl Display for Game {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "Queue: {}", self.piece_queue)?;
         if let Some(hold) = self.hold_piece {
-            write!(f, "Hold: {}\n", piece_type_to_string(hold))?;
+            writeln!(f, "Hold: {}", piece_type_to_string(hold))?;
         } else {
-            write!(f, "Hold: None\n")?;
+            writeln!(f, "Hold: None")?;
         }
 
         write!(f, "{}", self.board.display_with_active(&self.active_piece))?;
@@ -52,7 +57,7 @@ impl Game {
 
     pub fn from_rules(seed: Option<usize>, game_rules: GameRules) -> Self {
         let mut out = Self {
-            piece_queue: PieceQueue::new(seed),
+            piece_queue: PieceQueue::new_alt_randomizer(seed, game_rules.bag_type.clone()),
             game_rules,
             ..Default::default()
         };
@@ -70,17 +75,31 @@ impl Game {
     }
 
     pub fn get_hold_piece_or_next(&self) -> Piece {
-        return if let Some(piece) = self.hold_piece {
+        if let Some(piece) = self.hold_piece {
             Piece::new(piece)
         } else {
             Piece::new(self.piece_queue.peek())
-        };
+        }
     }
 
     pub fn set_active_piece(&mut self, new_piece: Piece) {
         self.active_piece = new_piece;
     }
 
+    // rules
+    pub fn get_game_rules(&self) -> &GameRules {
+        &self.game_rules
+    }
+
+    pub fn lock_command(&self) -> Command {
+        // without hard drop, the piece is soft dropped and left to lock
+        if self.game_rules.allow_hard_drop {
+            Command::HardDrop
+        } else {
+            Command::SoftDrop
+        }
+    }
+
     // game over
     pub fn get_game_over(&self) -> bool {
         self.game_data.game_over
@@ -109,21 +128,42 @@ impl Game {
         Game::ret_move_piece(&self.active_piece.clone(), &self.board, PointVector(0, 1))
     }
 
+    pub fn active_das_left(&mut self) -> bool {
+        let out = self.active_left();
+        while out && self.active_left() {}
+        out
+    }
+
+    pub fn active_das_right(&mut self) -> bool {
+        let out = self.active_right();
+        while out && self.active_right() {}
+        out
+    }
+
     fn active_down(&mut self) -> bool {
         Game::move_piece(&mut self.active_piece, &self.board, PointVector(-1, 0))
     }
 
     pub fn active_drop(&mut self) -> bool {
-        let out = self.active_down();
-        while out && self.active_down() {}
+        let distance = self.board.drop_distance(&self.active_piece);
+        self.active_piece.unsafe_move(PointVector(-(distance as i8), 0));
+        distance > 0
+    }
+
+    pub fn active_soft_drop(&mut self) -> bool {
+        // moving down loses the spin from the last rotation, unlike a hard drop
+        let out = self.active_drop();
+        if out {
+            self.active_piece.set_kick(999);
+        }
         out
     }
 
     pub fn ret_active_drop(&mut self) -> Piece {
         // TODO: make not mut
-        let save = self.active_piece.clone();
+        let save = self.active_piece;
         self.active_drop();
-        let out = self.active_piece.clone();
+        let out = self.active_piece;
         self.active_piece = save;
         out
     }
@@ -153,7 +193,7 @@ impl Game {
     }
 
     pub fn ret_active_cw(&mut self) -> Option<Piece> {
-        Game::ret_rotate_piece(&self.active_piece, &self.board, 1)
+        Game::ret_rotate_piece(&self.active_piece, &self.board, 1, &self.game_rules.kick_set)
     }
 
     pub fn active_180(&mut self) -> bool {
@@ -161,7 +201,7 @@ impl Game {
     }
 
     pub fn ret_active_180(&mut self) -> Option<Piece> {
-        Game::ret_rotate_piece(&self.active_piece, &self.board, 2)
+        Game::ret_rotate_piece(&self.active_piece, &self.board, 2, &self.game_rules.kick_set)
     }
 
     pub fn active_ccw(&mut self) -> bool {
@@ -172,21 +212,28 @@ impl Game {
     }
 
     pub fn ret_active_ccw(&mut self) -> Option<Piece> {
-        Game::ret_rotate_piece(&self.active_piece, &self.board, 3)
+        Game::ret_rotate_piece(&self.active_piece, &self.board, 3, &self.game_rules.kick_set)
     }
 
     pub fn active_piece_rotate_direction(&mut self, direction: RotationDirection) -> bool {
-        Game::rotate_piece(&mut self.active_piece, &self.board, direction)
+        Game::rotate_piece(&mut self.active_piece, &self.board, direction, &self.game_rules.kick_set)
     }
 
-    fn rotate_piece(p: &mut Piece, b: &Board, dir: RotationDirection) -> bool {
+    fn rotate_piece(p: &mut Piece, b: &Board, dir: RotationDirection, kick_set: &KickSet) -> bool {
         if dir == 0 {
             return true;
         }
         p.rotate(dir);
-        for (index, kick) in p.get_kicks(dir).iter().enumerate() {
+        let kicks = p.get_kicks(dir, kick_set);
+        for (index, kick) in kicks.iter().enumerate() {
+            if index > 0
+                && matches!(kick_set, KickSet::ARS)
+                && Game::ars_center_column_blocked(p, b, kicks[0])
+            {
+                break;
+            }
             if p.moved(*kick) {
-                if b.piece_valid_location(&p) {
+                if b.piece_valid_location(p) {
                     p.set_kick(index);
                     return true;
                 } else {
@@ -198,15 +245,56 @@ impl Game {
         false
     }
 
-    fn ret_rotate_piece(p: &Piece, b: &Board, dir: RotationDirection) -> Option<Piece> {
-        let mut piece = p.clone();
-        if Game::rotate_piece(&mut piece, b, dir) {
+    fn ars_center_column_blocked(p: &Piece, b: &Board, unkicked: PointVector) -> bool {
+        // L, J and T may not kick when the first blocked cell (in reading order) of the
+        // unkicked rotation is in the center column
+        // only the rotated piece's own cells are checked, which is the classic center column rule,
+        // the floor kicks and i kicks later tgm games added aren't modelled
+        if ![1, 5, 6].contains(&p.get_type()) {
+            return false;
+        }
+        let center = p.get_center().add(&Point(unkicked.0, unkicked.1));
+        let mut cells = p.abs_locations().unwrap();
+        cells.sort_by_key(|cell| (-cell.0, cell.1));
+        cells
+            .iter()
+            .map(|cell| cell.add(&Point(unkicked.0, unkicked.1)))
+            .find(|cell| !Board::in_bounds(cell.0 as usize, cell.1 as usize) || b.get(cell.0 as usize, cell.1 as usize))
+            .is_some_and(|cell| cell.1 == center.1)
+    }
+
+    fn ret_rotate_piece(p: &Piece, b: &Board, dir: RotationDirection, kick_set: &KickSet) -> Option<Piece> {
+        let mut piece = *p;
+        if Game::rotate_piece(&mut piece, b, dir, kick_set) {
             return Some(piece);
         }
         None
     }
 
     // versus
+    // spins of any piece, as the room's spin bonus counts them
+    pub fn get_spin_type(&self, piece: &Piece) -> TSpinType {
+        let rotated = piece.get_last_kick() != 999;
+        let t_spin = Game::get_t_spin_type(piece, &self.board);
+        match self.game_rules.spin_bonus {
+            SpinBonus::None => TSpinType::None,
+            SpinBonus::TSpin => t_spin,
+            SpinBonus::All if t_spin != TSpinType::None => t_spin,
+            SpinBonus::All if rotated && Game::immobile(piece, &self.board) => TSpinType::Full,
+            SpinBonus::All => TSpinType::None,
+            // any rotation counts, the piece just has to be stuck for a full one
+            SpinBonus::Stupid if !rotated => TSpinType::None,
+            SpinBonus::Stupid if t_spin == TSpinType::Full || Game::immobile(piece, &self.board) => TSpinType::Full,
+            SpinBonus::Stupid => TSpinType::Mini,
+        }
+    }
+
+    fn immobile(piece: &Piece, board: &Board) -> bool {
+        [PointVector(0, -1), PointVector(0, 1), PointVector(1, 0)]
+            .into_iter()
+            .all(|v| piece.ret_moved(v).is_none_or(|moved| board.piece_collision(&moved)))
+    }
+
     pub fn get_t_spin_type(piece: &Piece, board: &Board) -> TSpinType {
         if piece.get_type() != 6 {
             return TSpinType::None;
@@ -219,15 +307,13 @@ impl Game {
 
         let front_count = front
             .iter()
-            .map(|x| x.add_to_point(&piece.get_center()))
-            .flatten()
+            .filter_map(|x| x.add_to_point(&piece.get_center()))
             .filter(|x| board.get(x.0 as usize, x.1 as usize))
             .count();
 
         let mut back_count = back
             .iter()
-            .map(|x| x.add_to_point(&piece.get_center()))
-            .flatten()
+            .filter_map(|x| x.add_to_point(&piece.get_center()))
             .filter(|x| board.get(x.0 as usize, x.1 as usize))
             .count();
 
@@ -238,7 +324,7 @@ impl Game {
             back_count += 2;
         }
 
-        return if (front_count == 2 && back_count >= 1)
+        if (front_count == 2 && back_count >= 1)
             || (front_count == 1 && back_count >= 2 && piece.get_last_kick() == 4)
         {
             TSpinType::Full
@@ -246,7 +332,23 @@ impl Game {
             TSpinType::Mini
         } else {
             TSpinType::None
-        };
+        }
+    }
+
+    // hashing
+    pub fn state_hash(&self) -> u64 {
+        // everything that decides what can happen next, but not how we got here
+        self.board.state_hash()
+            ^ ACTIVE_KEYS[self.active_piece.get_type()]
+            ^ HOLD_KEYS[self.hold_piece.unwrap_or(NUM_PIECES)]
+            ^ mix(B2B_KEY ^ self.game_data.b2b as u64)
+            ^ mix(COMBO_KEY ^ self.game_data.combo as u64)
+            ^ mix(SURGE_KEY ^ self.game_data.surge as u64)
+            ^ self.piece_queue.state_hash()
+            // pending garbage in order, each attack by its size and hole
+            ^ self.garbage_queue.iter().fold(GARBAGE_KEY, |hash, garbage| {
+                mix(hash ^ ((garbage.lines as u64) << 8 | garbage.hole as u64))
+            })
     }
 
     // other
@@ -265,13 +367,17 @@ impl Game {
 
     pub fn hard_drop(&mut self) -> bool {
         self.active_drop();
-        return self.set_piece()
+        self.set_piece()
     }
 
     pub fn set_piece(&mut self) -> bool {
         if self
             .board
-            .top_out(&self.active_piece, &Piece::new(self.piece_queue.peek()))
+            .top_out(
+                &self.active_piece,
+                &Piece::new(self.piece_queue.peek()),
+                self.game_rules.max_board_height,
+            )
         {
             return false;
         }
@@ -280,16 +386,59 @@ impl Game {
         self.update();
         self.active_piece = self.piece_queue.next();
 
-        true
+        !self.get_game_over() && !self.board.piece_collision(&self.active_piece)
     }
 
     pub fn update(&mut self) {
-        let t_spin_type = Game::get_t_spin_type(&self.active_piece, &self.board);
+        let spin_type = self.get_spin_type(&self.active_piece);
         let lines_cleared = self.board.clear_lines();
-        let attack_type = attack_type(t_spin_type, lines_cleared);
+        let attack_type = attack_type(spin_type, lines_cleared, self.active_piece.get_type());
 
         self.game_data
-            .update(lines_cleared, attack_type, self.board.all_clear());
+            .update(lines_cleared, attack_type, self.board.all_clear(), &self.game_rules);
+
+        if lines_cleared > 0 {
+            self.cancel_garbage();
+        } else if !self.insert_garbage() {
+            self.set_game_over(true);
+        }
+    }
+
+    // garbage
+    pub fn receive_garbage(&mut self, lines: usize, hole: usize) {
+        if lines > 0 {
+            self.garbage_queue.push_back(Garbage { lines, hole });
+        }
+    }
+
+    pub fn get_pending_garbage(&self) -> usize {
+        self.garbage_queue.iter().map(|garbage| garbage.lines).sum()
+    }
+
+    fn cancel_garbage(&mut self) {
+        // outgoing attack cancels the oldest incoming garbage first
+        let mut attack = self.game_data.last_sent as usize;
+        while attack > 0 {
+            let Some(garbage) = self.garbage_queue.front_mut() else {
+                break;
+            };
+            let cancelled = attack.min(garbage.lines);
+            garbage.lines -= cancelled;
+            attack -= cancelled;
+            if garbage.lines == 0 {
+                self.garbage_queue.pop_front();
+            }
+        }
+        self.game_data.last_cancelled = self.game_data.last_sent - attack as u8;
+        self.game_data.last_sent = attack as u8;
+    }
+
+    fn insert_garbage(&mut self) -> bool {
+        let mut out = true;
+        while let Some(garbage) = self.garbage_queue.pop_front() {
+            out &= self.board.add_garbage(garbage.lines, garbage.hole);
+        }
+        out
     }
 }
 
@@ -309,15 +458,21 @@ pub mod game_rules_and_data {
         pub lines_cleared: usize,
         pub lines_sent: u16,
         pub last_sent: u8,
+        pub last_cancelled: u8,
         pub last_cleared: usize,
+        // charged by b2b in rooms that charge, sent when the b2b breaks
+        pub surge: u8,
+        pub last_surge: u8,
 
         pub t_spin: bool,
 
         pub game_over: bool,
+        // how long the game has run, for the garbage multiplier, kept by whoever runs the game
+        pub seconds: f32,
     }
 
     impl GameData {
-        pub fn update(&mut self, lines_cleared: usize, attack: AttackType, all_clear: bool) {
+        pub fn update(&mut self, lines_cleared: usize, attack: AttackType, all_clear: bool, rules: &GameRules) {
             self.pieces_placed += 1;
 
             if lines_cleared == 0 {
@@ -325,24 +480,35 @@ pub mod game_rules_and_data {
                 self.all_clear = false;
                 self.last_cleared = 0;
                 self.last_sent = 0;
+                self.last_cancelled = 0;
+                self.last_surge = 0;
                 return;
             }
 
             self.lines_cleared += lines_cleared;
             self.last_cleared = lines_cleared;
+            self.last_surge = 0;
 
             if attack == TD{
                 self.t_spin = true;
             }
 
             // update lines sent before adding b2b/combo
-            let lines_sent = calc_damage(self, attack, lines_cleared);
-            self.lines_sent += lines_sent as u16;
-            self.last_sent = lines_sent as u8;
+            let lines_sent = calc_damage(self, rules, attack, all_clear);
+            self.lines_sent = self.lines_sent.saturating_add(lines_sent.min(u16::MAX as usize) as u16);
+            self.last_sent = lines_sent.min(u8::MAX as usize) as u8;
             let b2b = BACK_TO_BACK_TYPES.contains(&attack);
             if b2b {
                 self.b2b += 1;
+                if rules.allow_b2b_charge {
+                    self.surge = rules.damage.surge(self.b2b, self.seconds).min(u8::MAX as usize) as u8;
+                }
             } else {
+                // breaking a charged b2b lets the surge out along with the clear
+                self.lines_sent = self.lines_sent.saturating_add(self.surge as u16);
+                self.last_sent = self.last_sent.saturating_add(self.surge);
+                self.last_surge = self.surge;
+                self.surge = 0;
                 self.b2b = 0;
             }
             self.combo += 1;
@@ -351,15 +517,24 @@ pub mod game_rules_and_data {
         }
     }
 
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Garbage {
+        pub lines: usize,
+        pub hole: usize,
+    }
+
     #[derive(Clone)]
     pub struct GameRules {
         pub bag_type: BagType,
         pub allow_180: bool,
         pub allow_hard_drop: bool,
         pub allow_b2b_chain: bool,
+        // tetr.io sets these apart, a room can charge a surge with or without chaining
+        pub allow_b2b_charge: bool,
         pub max_board_height: usize,
         pub kick_set: KickSet,
         pub spin_bonus: SpinBonus,
+        pub damage: DamageModel,
     }
 
     impl Default for GameRules {
@@ -369,16 +544,20 @@ pub mod game_rules_and_data {
                 allow_180: true,
                 allow_hard_drop: true,
                 allow_b2b_chain: true,
+                allow_b2b_charge: false,
                 max_board_height: MAX_PLACE_HEIGHT,
                 kick_set: Default::default(),
-                spin_bonus: Default::default()
+                spin_bonus: Default::default(),
+                damage: Default::default(),
             }
         }
     }
 
-    #[derive(Clone)]
+    #[derive(Clone, Default)]
+    #[allow(clippy::upper_case_acronyms)]
     pub enum KickSet {
         None,
+        #[default]
         SRSPlus,
         SRS,
         SRSX,
@@ -409,14 +588,9 @@ pub mod game_rules_and_data {
         }
     }
 
-    impl Default for KickSet {
-        fn default() -> Self {
-            KickSet::SRSPlus
-        }
-    }
-
-    #[derive(Clone)]
+    #[derive(Clone, Default)]
     pub enum SpinBonus {
+        #[default]
         TSpin,
         All,
         Stupid,
@@ -432,19 +606,13 @@ pub mod game_rules_and_data {
                 "all" => SpinBonus::All,
                 "stupid" => SpinBonus::Stupid,
                 "none" => SpinBonus::None,
-                other => (|| {
+                other => {
                     eprintln!("unknown spinbonus '{}'", other);
                     SpinBonus::TSpin
-                })(),
+                },
             })
         }
     }
-
-    impl Default for SpinBonus {
-        fn default() -> Self {
-            SpinBonus::TSpin
-        }
-    }
 }
 
 #[cfg(test)]
@@ -460,4 +628,418 @@ pub mod game_test {
 
         // assert!(false);
     }
+
+    fn kick_game(kick_set: KickSet, piece_type: PieceType, rotation_state: usize, center: Point) -> Game {
+        let mut game = Game::from_rules(
+            Some(1),
+            GameRules {
+                kick_set,
+                ..Default::default()
+            },
+        );
+        game.set_active_piece(Piece {
+            piece_type,
+            rotation_state,
+            center,
+            last_kick: 0,
+        });
+        game
+    }
+
+    #[test]
+    pub fn test_rules_commands() {
+        use crate::players::do_command;
+
+        let mut game = Game::from_rules(
+            Some(1),
+            GameRules {
+                allow_180: false,
+                allow_hard_drop: false,
+                ..Default::default()
+            },
+        );
+        assert_eq!(game.lock_command(), Command::SoftDrop);
+        assert!(!do_command(&mut game, Command::Rotate180));
+        assert!(!do_command(&mut game, Command::HardDrop));
+        assert_eq!(game.game_data.pieces_placed, 0);
+
+        // first soft drop moves the piece down, second one locks it
+        assert!(do_command(&mut game, Command::SoftDrop));
+        assert_eq!(game.game_data.pieces_placed, 0);
+        assert!(do_command(&mut game, Command::SoftDrop));
+        assert_eq!(game.game_data.pieces_placed, 1);
+    }
+
+    #[test]
+    pub fn test_max_board_height() {
+        let mut game = Game::from_rules(
+            Some(1),
+            GameRules {
+                max_board_height: 10,
+                ..Default::default()
+            },
+        );
+        for row in 0..10 {
+            game.board.add(row, 0);
+        }
+        game.set_active_piece(Piece {
+            piece_type: 4,
+            rotation_state: 1,
+            center: Point(12, 0),
+            last_kick: 0,
+        });
+        assert!(!game.set_piece());
+
+        let mut game = Game::new(Some(1));
+        for row in 0..10 {
+            game.board.add(row, 0);
+        }
+        game.set_active_piece(Piece {
+            piece_type: 4,
+            rotation_state: 1,
+            center: Point(12, 0),
+            last_kick: 0,
+        });
+        assert!(game.set_piece());
+    }
+
+    fn spin_game(spin_bonus: SpinBonus, last_kick: usize) -> Game {
+        // an s stuck in a pocket two rows deep under a roof, filling both rows
+        let mut game = Game::from_rules(
+            Some(1),
+            GameRules {
+                spin_bonus,
+                ..Default::default()
+            },
+        );
+        let piece = Piece {
+            center: Point(0, 4),
+            last_kick,
+            ..Piece::new(3)
+        };
+        let cells = piece.abs_locations().unwrap();
+        for row in 0..3 {
+            for col in 0..9 {
+                if !cells.contains(&Point(row as i8, col as i8)) {
+                    game.board.add(row, col);
+                }
+            }
+        }
+        game.board.add(0, 9);
+        game.board.add(1, 9);
+        game.set_active_piece(piece);
+        game
+    }
+
+    #[test]
+    pub fn test_all_spin() {
+        let free = Piece {
+            last_kick: 0,
+            ..Piece::new(3)
+        };
+        for (spin_bonus, stuck, loose) in [
+            (SpinBonus::TSpin, TSpinType::None, TSpinType::None),
+            (SpinBonus::All, TSpinType::Full, TSpinType::None),
+            (SpinBonus::Stupid, TSpinType::Full, TSpinType::Mini),
+            (SpinBonus::None, TSpinType::None, TSpinType::None),
+        ] {
+            let game = spin_game(spin_bonus.clone(), 0);
+            assert_eq!(game.get_spin_type(game.get_active_piece()), stuck);
+            assert_eq!(game.get_spin_type(&free), loose);
+
+            // shifted in, not rotated
+            let game = spin_game(spin_bonus, 999);
+            assert_eq!(game.get_spin_type(game.get_active_piece()), TSpinType::None);
+        }
+
+        // an s-spin double hits like a tsd, and keeps b2b going
+        let mut game = spin_game(SpinBonus::All, 0);
+        game.set_piece();
+        assert_eq!(game.game_data.last_cleared, 2);
+        assert_eq!(game.game_data.last_sent, 4);
+        assert_eq!(game.game_data.b2b, 1);
+
+        let mut game = spin_game(SpinBonus::TSpin, 0);
+        game.set_piece();
+        assert_eq!(game.game_data.last_sent, 1);
+        assert_eq!(game.game_data.b2b, 0);
+        assert_eq!(attack_type(TSpinType::Mini, 1, 4), AttackType::SSM);
+        assert_eq!(attack_type(TSpinType::Full, 3, 6), AttackType::TT);
+    }
+
+    #[test]
+    pub fn test_surge() {
+        let charging = GameRules {
+            allow_b2b_chain: false,
+            allow_b2b_charge: true,
+            ..Default::default()
+        };
+        let mut data = GameData::default();
+        for quad in 0..6 {
+            data.update(4, AttackType::Q, false, &charging);
+            // a flat line of b2b bonus once the chain has started
+            assert_eq!(data.last_sent, if quad == 0 { 4 } else { 5 });
+            // a placement in between drops the combo but keeps b2b
+            data.update(0, AttackType::None, false, &charging);
+        }
+        assert_eq!(data.surge, 5);
+
+        // a plain double breaks it, sending the surge with it
+        data.update(2, AttackType::D, false, &charging);
+        assert_eq!((data.last_sent, data.last_surge, data.surge, data.b2b), (6, 5, 0, 0));
+
+        // rooms that don't charge never do, whether they chain or not
+        let mut data = GameData::default();
+        for _ in 0..6 {
+            data.update(4, AttackType::Q, false, &GameRules::default());
+            data.update(0, AttackType::None, false, &GameRules::default());
+        }
+        assert_eq!(data.surge, 0);
+        data.update(2, AttackType::D, false, &GameRules::default());
+        assert_eq!(data.last_sent, 1);
+
+        // and a room can chain and charge at once
+        let both = GameRules {
+            allow_b2b_charge: true,
+            ..Default::default()
+        };
+        let mut data = GameData::default();
+        let mut sent = 0;
+        for _ in 0..6 {
+            data.update(4, AttackType::Q, false, &both);
+            sent = data.last_sent;
+            data.update(0, AttackType::None, false, &both);
+        }
+        assert_eq!((sent, data.surge), (6, 5));
+
+        // a big surge on a big clear stops at what a u8 holds
+        let heavy = GameRules {
+            damage: DamageModel {
+                garbage_multiplier: 100.0,
+                ..Default::default()
+            },
+            ..charging
+        };
+        let mut data = GameData {
+            b2b: 10,
+            surge: 200,
+            ..Default::default()
+        };
+        data.update(2, AttackType::D, false, &heavy);
+        assert_eq!((data.last_sent, data.last_surge), (u8::MAX, 200));
+    }
+
+    #[test]
+    pub fn test_garbage_increase() {
+        // the multiplier grows by half a line a second after 3 seconds
+        let rules = GameRules {
+            damage: DamageModel {
+                garbage_increase: 0.5,
+                margin_time: 3.0,
+                all_clear_bonus: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut game = Game::from_rules(Some(1), rules);
+        let double = |game: &mut Game| {
+            for row in 0..2 {
+                game.board.set_row(row, vec![true; crate::constants::board_constants::BOARD_WIDTH]);
+            }
+            game.update();
+            game.game_data.last_sent
+        };
+
+        assert_eq!(double(&mut game), 1);
+        // nothing cleared, so the combo doesn't add to the next one
+        game.update();
+        game.game_data.seconds = 6.0;
+        assert_eq!(double(&mut game), 2);
+    }
+
+    #[test]
+    pub fn test_state_hash() {
+        let game = Game::new(Some(3));
+
+        // the same pieces placed in a different order give the same state
+        let mut a = game.clone();
+        let mut b = game.clone();
+        a.board.add_list(vec![Point(0, 0), Point(0, 1)]);
+        b.board.add_list(vec![Point(0, 1), Point(0, 0)]);
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        b.hold();
+        assert_ne!(a.state_hash(), b.state_hash());
+
+        let mut c = a.clone();
+        c.game_data.b2b = 1;
+        assert_ne!(a.state_hash(), c.state_hash());
+
+        let mut d = a.clone();
+        d.piece_queue.next();
+        assert_ne!(a.state_hash(), d.state_hash());
+
+        // pending garbage and a charged surge change what comes next too
+        let mut e = a.clone();
+        e.receive_garbage(2, 3);
+        assert_ne!(a.state_hash(), e.state_hash());
+        let mut f = a.clone();
+        f.receive_garbage(2, 4);
+        assert_ne!(e.state_hash(), f.state_hash());
+        let mut g = a.clone();
+        g.game_data.surge = 4;
+        assert_ne!(a.state_hash(), g.state_hash());
+    }
+
+    #[test]
+    pub fn test_garbage_queue() {
+        // the tetris below clears the board, leave the all clear bonus out of it
+        let rules = GameRules {
+            damage: DamageModel {
+                all_clear_bonus: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut game = Game::from_rules(Some(1), rules);
+        game.receive_garbage(2, 0);
+        game.receive_garbage(3, 5);
+        assert_eq!(game.get_pending_garbage(), 5);
+
+        // a tetris sends 4, cancelling the first attack and part of the second
+        for row in 0..4 {
+            game.board.set_row(row, vec![true, true, true, true, true, true, true, true, true, false]);
+        }
+        game.set_active_piece(Piece {
+            piece_type: 4,
+            rotation_state: 1,
+            center: Point(2, 9),
+            last_kick: 0,
+        });
+        assert!(game.set_piece());
+        assert_eq!(game.game_data.last_cleared, 4);
+        assert_eq!(game.game_data.last_cancelled, 4);
+        assert_eq!(game.game_data.last_sent, 0);
+        assert_eq!(game.garbage_queue, VecDeque::from([Garbage { lines: 1, hole: 5 }]));
+        assert_eq!(game.board.get_max_height(), 0);
+
+        // placing without clearing lets the rest in
+        game.set_active_piece(Piece {
+            piece_type: 2,
+            rotation_state: 0,
+            center: Point(0, 0),
+            last_kick: 0,
+        });
+        assert!(game.set_piece());
+        assert!(game.garbage_queue.is_empty());
+        assert_eq!(game.board.get_heights(), [3, 3, 1, 1, 1, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    pub fn test_srs_i_kicks() {
+        // first test is blocked, SRS+ tries right before SRS tries left
+        let mut game = kick_game(KickSet::SRSPlus, 4, 0, Point(21, 4));
+        game.board.add(20, 5);
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(21, 6));
+
+        let mut game = kick_game(KickSet::SRS, 4, 0, Point(21, 4));
+        game.board.add(20, 5);
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(21, 3));
+        assert_eq!(game.active_piece.last_kick, 1);
+    }
+
+    #[test]
+    pub fn test_180_kicks() {
+        // t piece flat on the floor has to kick up to rotate 180
+        let mut game = kick_game(KickSet::SRSPlus, 6, 0, Point(0, 4));
+        assert!(game.active_180());
+        assert_eq!(game.active_piece.center, Point(1, 4));
+
+        let mut game = kick_game(KickSet::SRS, 6, 0, Point(0, 4));
+        assert!(!game.active_180());
+        assert_eq!(game.active_piece.center, Point(0, 4));
+        assert_eq!(game.active_piece.rotation_state, 0);
+
+        let mut game = kick_game(KickSet::SRSX, 6, 0, Point(0, 4));
+        assert!(game.active_180());
+        assert_eq!(game.active_piece.center, Point(1, 5));
+
+        let mut game = kick_game(KickSet::ASC, 6, 0, Point(0, 4));
+        assert!(!game.active_180());
+    }
+
+    #[test]
+    pub fn test_tetra_x_kicks() {
+        let mut game = kick_game(KickSet::SRSPlus, 6, 0, Point(0, 4));
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(1, 3));
+
+        // tetra-x checks straight up before diagonals
+        let mut game = kick_game(KickSet::TetraX, 6, 0, Point(0, 4));
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(1, 4));
+        assert_eq!(game.active_piece.last_kick, 6);
+    }
+
+    #[test]
+    pub fn test_asc_kicks() {
+        // asc kicks are mirrored between cw and ccw
+        let mut game = kick_game(KickSet::ASC, 6, 0, Point(5, 4));
+        game.board.add(4, 4);
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(5, 3));
+
+        let mut game = kick_game(KickSet::ASC, 6, 0, Point(5, 4));
+        game.board.add(4, 4);
+        assert!(game.active_ccw());
+        assert_eq!(game.active_piece.center, Point(5, 5));
+
+        let mut game = kick_game(KickSet::ASC, 6, 0, Point(0, 4));
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(1, 4));
+        assert_eq!(game.active_piece.last_kick, 11);
+    }
+
+    #[test]
+    pub fn test_nrs_kicks() {
+        // nrs z only has two positions, rotating twice lands on the same cells
+        let mut game = kick_game(KickSet::NRS, 0, 0, Point(5, 4));
+        let start = game.active_piece.abs_locations().unwrap();
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(6, 4));
+        assert!(game.active_cw());
+        let end = game.active_piece.abs_locations().unwrap();
+        for cell in start {
+            assert!(end.contains(&cell));
+        }
+
+        // nothing kicks
+        let mut game = kick_game(KickSet::NRS, 6, 0, Point(0, 4));
+        assert!(!game.active_cw());
+        let mut game = kick_game(KickSet::None, 6, 0, Point(0, 4));
+        assert!(!game.active_cw());
+    }
+
+    #[test]
+    pub fn test_ars_kicks() {
+        // vertical i against the wall
+        let mut game = kick_game(KickSet::SRSPlus, 4, 1, Point(5, 0));
+        assert!(game.active_cw());
+        let mut game = kick_game(KickSet::ARS, 4, 1, Point(5, 0));
+        assert!(!game.active_cw());
+
+        // center column is blocked first, so no kick
+        let mut game = kick_game(KickSet::ARS, 6, 0, Point(5, 4));
+        game.board.add(7, 4);
+        assert!(!game.active_cw());
+
+        // side column is blocked first, kicks right (blocked) then left
+        let mut game = kick_game(KickSet::ARS, 6, 0, Point(5, 4));
+        game.board.add(6, 5);
+        assert!(game.active_cw());
+        assert_eq!(game.active_piece.center, Point(6, 3));
+        assert_eq!(game.active_piece.last_kick, 2);
+    }
 }
@@ -6,15 +6,23 @@ mod book;
 mod bot;
 mod communications;
 mod constants;
+mod finesse;
 mod game;
+mod monte_carlo;
 mod piece;
 mod players;
+mod protocol;
 mod point_vector;
 mod population;
 mod queue;
+mod search_tree;
+mod tournament;
 mod versus;
+mod versus_match;
 mod weight;
 mod opener;
+mod perfect_clear;
+mod tbp;
 
 use crate::bot::*;
 use crate::players::Player;
@@ -27,14 +35,47 @@ use crate::game::Game;
 use crate::piece::Piece;
 use crate::weight::Weights;
 use crate::point_vector::Point;
+use crate::communications::{Frontend, ServerConfig};
 use crate::opener::*;
+use crate::population::Population;
+use crate::tournament::{Pairing, Tournament};
+use crate::versus_match::Match;
 
 fn main() {
+    // logs go to stderr, RUST_LOG picks the level
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
     // bot_play();
     tetrio_play();
 
     // more_test();
     // dt_test();
+    // bot_versus();
+    // weight_tournament();
+    // tune_weights();
+}
+
+// weight files or profile names from the command line, the default weights otherwise
+fn weights_from_args() -> Vec<(String, Weights)> {
+    weights_from_names(std::env::args().skip(1).collect())
+}
+
+fn weights_from_names(mut names: Vec<String>) -> Vec<(String, Weights)> {
+    if names.is_empty() {
+        names.push("default".to_string());
+    }
+    names
+        .into_iter()
+        .map(|name| {
+            let weights = Weights::from_name_or_path(&name)
+                .unwrap_or_else(|e| panic!("could not load weights {}: {}", name, e));
+            (name, weights)
+        })
+        .collect()
+}
+
+fn first_weights_from_args() -> Weights {
+    weights_from_args().swap_remove(0).1
 }
 
 fn more_test() {
@@ -50,7 +91,7 @@ fn more_test() {
 
 }
 fn bot_play() {
-    let mut bot = Bot::default();
+    let mut bot = Bot::from_weights(Game::new(None), first_weights_from_args());
     println!("{}", bot.get_game().board.get_arr().len());
 
     let mut time = 0;
@@ -127,6 +168,58 @@ fn dt_test() {
     }
 }
 
+fn bot_versus() {
+    let mut weights = weights_from_args().into_iter().map(|(_, weights)| weights);
+    let one = weights.next().unwrap();
+    let two = weights.next().unwrap_or_else(|| one.clone());
+    let one = Bot::from_weights(Game::new(None), one);
+    let two = Bot::from_weights(Game::new(None), two);
+    let result = Match::new(7000, one, two, 500).play();
+    println!("winner: {:?}", result.winner);
+    for stats in result.stats {
+        println!(
+            "{} pieces, {:.2} app, {:.2} apm, {:.2} pps, {:.2} vs, {:?} per piece thinking",
+            stats.pieces,
+            stats.app(),
+            stats.apm(),
+            stats.pps(),
+            stats.vs(),
+            stats.think_per_piece()
+        );
+    }
+}
+
+fn weight_tournament() {
+    let mut entrants = weights_from_args();
+    if entrants.len() == 1 {
+        entrants.push(("default 2".to_string(), Weights::default()));
+    }
+    let report = Tournament::new(entrants, (0..4).collect(), Pairing::RoundRobin).run();
+    println!("{}", report);
+    println!("{}", report.to_json());
+}
+
+fn tune_weights() {
+    let path = std::path::Path::new("population.json");
+    let mut population = Population::load(path)
+        .unwrap_or_else(|_| Population::new(Default::default(), &first_weights_from_args(), 0));
+    for stats in population.evolve(10, Some(path)).unwrap() {
+        println!("generation {}: best fitness {}, mean {}", stats.generation, stats.best, stats.mean);
+    }
+    println!("{:?}", population.best().genome);
+}
+
 fn tetrio_play() {
-    communications::init()
+    // server options first, anything left over names the weights
+    let (config, names) = ServerConfig::from_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, ServerConfig::USAGE);
+        std::process::exit(2)
+    });
+    let weights = weights_from_names(names).swap_remove(0).1;
+    let stopped = match config.frontend.clone() {
+        Frontend::Tetrio => communications::init(config, weights),
+        Frontend::TbpStdio => tbp::run_stdio(weights),
+        Frontend::TbpWebsocket(address) => tbp::run_websocket(&address, weights),
+    };
+    stopped.unwrap_or_else(|e| panic!("server stopped: {}", e))
 }
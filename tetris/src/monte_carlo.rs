@@ -0,0 +1,235 @@
+#![allow(dead_code)]
+
+use crate::bot::Bot;
+use crate::constants::piece_constants::NUM_PIECES;
+use crate::constants::types::*;
+use crate::game::Game;
+use crate::piece::Piece;
+use crate::queue::BagType;
+use crate::search_tree::play;
+use crate::weight::Weights;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonteCarloConfig {
+    // rollouts in total, shared out between the first placements
+    pub iterations: usize,
+    // how many of the best first placements are worth rolling out
+    pub candidates: usize,
+    // placements per rollout, counting the first
+    pub depth: usize,
+    // queue pieces the bot gets to see, everything after is sampled
+    pub preview: usize,
+    // ucb exploration, relative to the spread of the first placement scores
+    pub exploration: f32,
+    // how much less the versus score of the deepest placement counts
+    pub discount: f32,
+    pub seed: u64,
+}
+
+impl Default for MonteCarloConfig {
+    fn default() -> Self {
+        Self {
+            iterations: 200,
+            candidates: 8,
+            depth: 6,
+            preview: 5,
+            exploration: 1.0,
+            discount: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+// a queue of `length` pieces, the preview as it is and the unseen pieces drawn the way the randomizer could
+pub fn sample_queue(game: &Game, preview: usize, length: usize, rng: &mut StdRng) -> VecDeque<PieceType> {
+    let mut queue: VecDeque<PieceType> = game.piece_queue.get_queue().iter().take(preview).copied().collect();
+
+    let copies = match game.get_game_rules().bag_type {
+        BagType::SevenBag => 1,
+        BagType::FourteenBag => 2,
+        _ => 0,
+    };
+    if copies == 0 {
+        // no bag to go by, every piece is as likely as any other
+        while queue.len() < length {
+            queue.push_back(rng.gen_range(0..NUM_PIECES));
+        }
+        queue.truncate(length);
+        return queue;
+    }
+
+    // bags start with the first piece of the game, so the count drawn gives the place in the current one
+    let size = NUM_PIECES * copies;
+    let held = game.hold_piece.is_some() as usize;
+    let drawn = game.game_data.pieces_placed + 1 + held + queue.len();
+    let seen = drawn % size;
+
+    let mut counts = [copies; NUM_PIECES];
+    let known: Vec<PieceType> = std::iter::once(game.active_piece.get_type()).chain(queue.iter().copied()).collect();
+    for &piece in known.iter().rev().take(seen) {
+        counts[piece] = counts[piece].saturating_sub(1);
+    }
+    let mut rest: Vec<PieceType> = (0..NUM_PIECES)
+        .flat_map(|piece| std::iter::repeat_n(piece, counts[piece]))
+        .collect();
+    rest.shuffle(rng);
+    // pieces of this bag drawn before the active one aren't known, any of the rest could be them
+    rest.truncate(size - seen);
+    queue.extend(rest);
+
+    let bag: Vec<PieceType> = (0..NUM_PIECES).flat_map(|piece| std::iter::repeat_n(piece, copies)).collect();
+    while queue.len() < length {
+        let mut next = bag.clone();
+        next.shuffle(rng);
+        queue.extend(next);
+    }
+    queue.truncate(length);
+    queue
+}
+
+// flat monte carlo tree search: ucb picks which of the best first placements to roll out next,
+// and each is valued by the average greedy rollout over sampled queues (expectimax over the unseen pieces)
+pub fn search(game: &Game, weights: &Weights, config: &MonteCarloConfig) -> Option<(CommandList, Piece, Score)> {
+    let mut dummy = game.clone();
+    let (moves, placements, scores) = Bot::move_placement_score_1d(&mut dummy, weights);
+    let totals: Vec<Score> = scores.iter().map(|(board, versus)| board + versus).collect();
+
+    let mut order: Vec<usize> = (0..totals.len()).collect();
+    order.sort_by(|&a, &b| totals[a].partial_cmp(&totals[b]).unwrap());
+    order.truncate(config.candidates.max(1));
+    let &first = order.first()?;
+
+    let spread = order.iter().map(|&i| totals[i] - totals[first]).fold(0.0, Score::max).max(1.0);
+    let exploration = config.exploration * spread;
+    // seeded by the position so the same game always gets the same answer
+    let mut rng = StdRng::seed_from_u64(config.seed ^ game.state_hash());
+    let mut sums = vec![0.0; order.len()];
+    let mut visits = vec![0usize; order.len()];
+
+    for iteration in 0..config.iterations.max(order.len()) {
+        // every candidate once, then by lower confidence bound since lower scores are better
+        let choice = if iteration < order.len() {
+            iteration
+        } else {
+            let log = (iteration as f32).ln();
+            (0..order.len())
+                .map(|i| (i, sums[i] / visits[i] as f32 - exploration * (log / visits[i] as f32).sqrt()))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .unwrap()
+                .0
+        };
+
+        let index = order[choice];
+        let queue = sample_queue(game, config.preview, config.depth + 1, &mut rng);
+        sums[choice] += rollout(game, &placements[index], scores[index], queue, weights, config);
+        visits[choice] += 1;
+    }
+
+    let best = (0..order.len())
+        .min_by(|&a, &b| (sums[a] / visits[a] as f32).partial_cmp(&(sums[b] / visits[b] as f32)).unwrap())?;
+    let index = order[best];
+    Some((moves[index].clone(), placements[index], sums[best] / visits[best] as f32))
+}
+
+fn rollout(
+    game: &Game,
+    first: &Piece,
+    score: (Score, Score),
+    queue: VecDeque<PieceType>,
+    weights: &Weights,
+    config: &MonteCarloConfig,
+) -> Score {
+    let mut game = game.clone();
+    game.piece_queue.set_queue(queue);
+    play(&mut game, first);
+
+    let (mut board, mut versus) = score;
+    for step in 1..config.depth {
+        let (_, placements, scores) = Bot::move_placement_score_1d(&mut game, weights);
+        let Some(best) = (0..scores.len())
+            .min_by(|&a, &b| (scores[a].0 + scores[a].1).partial_cmp(&(scores[b].0 + scores[b].1)).unwrap())
+        else {
+            // topped out
+            return Score::INFINITY;
+        };
+
+        let discount = 1.0 - (config.discount * step as f32 / config.depth as f32);
+        board = scores[best].0;
+        versus += scores[best].1 * discount;
+        play(&mut game, &placements[best]);
+    }
+    board + versus
+}
+
+#[cfg(test)]
+mod monte_carlo_tests {
+    use super::*;
+    use crate::bot::Strategy;
+    use crate::game::game_rules_and_data::GameRules;
+    use crate::players::Player;
+
+    fn counts(pieces: &[PieceType]) -> [usize; NUM_PIECES] {
+        let mut out = [0; NUM_PIECES];
+        for &piece in pieces {
+            out[piece] += 1;
+        }
+        out
+    }
+
+    #[test]
+    fn test_seven_bag() {
+        let game = Game::new(Some(4));
+        let mut rng = StdRng::seed_from_u64(1);
+        let queue = sample_queue(&game, 5, 20, &mut rng);
+        assert_eq!(queue.len(), 20);
+        assert!(queue.iter().take(5).eq(game.piece_queue.get_queue().iter().take(5)));
+
+        // the active piece starts the first bag, every bag after it is whole
+        let sequence: Vec<PieceType> = std::iter::once(game.active_piece.get_type()).chain(queue).collect();
+        for bag in sequence.chunks_exact(7) {
+            assert_eq!(counts(bag), [1; NUM_PIECES]);
+        }
+    }
+
+    #[test]
+    fn test_fourteen_bag() {
+        let rules = GameRules {
+            bag_type: BagType::FourteenBag,
+            ..Default::default()
+        };
+        let mut game = Game::from_rules(Some(4), rules);
+        game.set_active_piece(Piece::new(0));
+        game.piece_queue.set_queue(VecDeque::from([0, 1, 1, 2, 2]));
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let queue: Vec<PieceType> = sample_queue(&game, 5, 27, &mut rng).into();
+        assert_eq!(counts(&queue[5..13]), [0, 0, 0, 2, 2, 2, 2]);
+        assert_eq!(counts(&queue[13..]), [2; NUM_PIECES]);
+    }
+
+    #[test]
+    fn test_search() {
+        let config = MonteCarloConfig {
+            iterations: 12,
+            candidates: 3,
+            depth: 3,
+            ..Default::default()
+        };
+        let game = Game::new(Some(2));
+        let weights = Weights::default();
+        let (_, placement, _) = search(&game, &weights, &config).unwrap();
+        assert_eq!(search(&game, &weights, &config).unwrap().1, placement);
+
+        let mut bot = Bot::new(game);
+        bot.set_strategy(Strategy::MonteCarlo(config));
+        for _ in 0..3 {
+            assert!(bot.make_move());
+        }
+        assert_eq!(bot.get_game().game_data.pieces_placed, 3);
+        assert!(bot.get_search_tree().is_none());
+    }
+}
@@ -12,13 +12,15 @@ pub struct Dependency {
     pub dependency: Vec<PieceType>
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default)]
 pub enum OpenerStatus {
+    #[default]
     New,
     Active,
     Invalid,
 }
 
+#[derive(Default)]
 pub struct Opener {
     pub opener_sequence: OpenerSequence,
     pub dependencies_list: Vec<Vec<Dependencies>>,
@@ -29,27 +31,6 @@ pub struct Opener {
     pub status: OpenerStatus,
 }
 
-impl Default for OpenerStatus {
-    fn default() -> Self {
-        OpenerStatus::New
-    }
-}
-
-
-impl Default for Opener {
-    fn default() -> Self {
-        Self {
-            opener_sequence: vec![],
-            dependencies_list: vec![],
-            bag: 0,
-            bag_progress: 0,
-            variant: 0,
-            piece_order: vec![],
-            status: Default::default()
-        }
-    }
-}
-
 impl Opener {
     pub fn new(opener_sequence: OpenerSequence, dependencies_list: Vec<Vec<Dependencies>>) -> Self {
         Self {
@@ -60,32 +41,35 @@ impl Opener {
     }
 
     pub fn init(&mut self, queue: &PieceOrder) {
-        if self.opener_sequence.len() == 0 {
+        if self.opener_sequence.is_empty() {
             self.status = OpenerStatus::Invalid;
         } else if self.solve_bag(queue) {
             self.status = OpenerStatus::Active
         } else {
             self.status = OpenerStatus::Invalid
         }
-        println!("With queue {:?}, Opener is {:?}", queue, self.status);
+        eprintln!("With queue {:?}, Opener is {:?}", queue, self.status);
     }
 
     pub fn bag_number(&self) -> BagNumber {
         self.bag
     }
 
-    pub fn next_placement(&mut self, queue: &PieceOrder) -> Piece {
-        let out = self.opener_sequence[self.bag][self.variant][self.piece_order[self.bag_progress]].clone();
+    pub fn next_placement(&self) -> Piece {
+        self.opener_sequence[self.bag][self.variant][self.piece_order[self.bag_progress]]
+    }
+
+    // once the next placement is played
+    pub fn advance(&mut self, queue: &PieceOrder) {
         self.bag_progress += 1;
         if self.bag_progress == NUM_PIECES {
             self.bag_progress = 0;
             self.bag += 1;
-            println!("{}, {}", self.bag, self.opener_sequence.len());
+            eprintln!("{}, {}", self.bag, self.opener_sequence.len());
             if self.bag >= self.opener_sequence.len() || !self.solve_bag(queue) {
                 self.status = OpenerStatus::Invalid;
             }
         }
-        return out;
     }
 
     fn satisfy_dependency(queue: &PieceOrder, dependency: &Dependency) -> bool {
@@ -103,7 +87,7 @@ impl Opener {
     }
 
     fn queue_variations(queue: PieceOrder, hold: PieceType) -> Vec<PieceOrder> {
-        if queue.len() < 1 {
+        if queue.is_empty() {
             vec![vec![hold]]
         } else {
             let mut out = Vec::new();
@@ -139,7 +123,7 @@ impl Opener {
                 return true;
             }
         }
-        println!("unable to solve");
+        eprintln!("unable to solve");
         false
     }
 
@@ -0,0 +1,269 @@
+#![allow(dead_code)]
+
+use crate::board::Board;
+use crate::bot::Bot;
+use crate::constants::board_constants::{BOARD_HEIGHT, BOARD_WIDTH};
+use crate::constants::types::*;
+use crate::game::Game;
+use crate::piece::Piece;
+use crate::search_tree::{consistent, play};
+use std::collections::HashSet;
+
+// fields proven unsolvable are forgotten past this many
+const MAX_CACHED: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PcConfig {
+    // the highest all clear tried, in rows
+    pub max_height: usize,
+    // queue pieces the solver gets to plan with
+    pub preview: usize,
+}
+
+impl Default for PcConfig {
+    fn default() -> Self {
+        Self {
+            max_height: 4,
+            preview: 5,
+        }
+    }
+}
+
+// everything a search from a field depends on
+type FieldKey = (BoardArray, PieceType, Option<PieceType>, Vec<PieceType>, usize);
+
+#[derive(Debug, Clone, Default)]
+pub struct PerfectClear {
+    config: PcConfig,
+    // fields already searched without finding an all clear
+    failed: HashSet<FieldKey>,
+    // what's left of the solution being played
+    plan: PlacementList,
+}
+
+impl PerfectClear {
+    // init
+    pub fn new(config: PcConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    // getters
+    pub fn get_config(&self) -> &PcConfig {
+        &self.config
+    }
+
+    pub fn get_plan(&self) -> &PlacementList {
+        &self.plan
+    }
+
+    // the next placement of an all clear from this game, keeping to the last solution while it still fits
+    pub fn next_placement(&mut self, game: &Game) -> Option<Piece> {
+        let fits = self
+            .plan
+            .first()
+            .is_some_and(|p| game.board.piece_valid_placement(p) && consistent(game, &self.plan));
+        if !fits {
+            self.plan = self.solve(game)?;
+        }
+        self.plan.first().copied()
+    }
+
+    // once a placement is played, the plan moves on if it was the one planned and is dropped if not
+    pub fn commit(&mut self, placement: &Piece) {
+        if self.plan.first() == Some(placement) {
+            self.plan.remove(0);
+        } else {
+            self.plan.clear();
+        }
+    }
+
+    // placements, in order, that clear the board from this game using only the known queue
+    pub fn solve(&mut self, game: &Game) -> Option<PlacementList> {
+        if self.failed.len() > MAX_CACHED {
+            self.failed.clear();
+        }
+
+        let mut game = game.clone();
+        // the solver can't plan around garbage it doesn't know the holes of yet
+        game.garbage_queue.clear();
+        let known = game.piece_queue.get_queue().len().min(self.config.preview);
+        let minos = game.board.get_mino_count();
+
+        for height in game.board.get_max_height().max(1)..=self.config.max_height {
+            if !(BOARD_WIDTH * height - minos).is_multiple_of(4) {
+                continue;
+            }
+            let mut placements = PlacementList::new();
+            if self.search(&game, height, known, &mut placements) {
+                return Some(placements);
+            }
+        }
+        None
+    }
+
+    fn search(&mut self, game: &Game, height: usize, known: usize, placements: &mut PlacementList) -> bool {
+        let cells = BOARD_WIDTH * height - game.board.get_mino_count();
+        let available = 1 + known + game.hold_piece.is_some() as usize;
+        if !cells.is_multiple_of(4) || cells / 4 > available || !regions_fit(&game.board, height) {
+            return false;
+        }
+
+        let queue = game.piece_queue.get_queue().iter().take(known).copied().collect();
+        let key = (game.board.get_arr(), game.active_piece.get_type(), game.hold_piece, queue, height);
+        if self.failed.contains(&key) {
+            return false;
+        }
+
+        // the active piece, then whatever hold gives, taking the next piece off the queue if it's empty
+        let mut options = vec![(*game.get_active_piece(), 1)];
+        let hold = game.get_hold_piece_or_next();
+        if hold.get_type() != game.active_piece.get_type() && (game.hold_piece.is_some() || known > 0) {
+            options.push((hold, 1 + game.hold_piece.is_none() as usize));
+        }
+
+        let mut seen = HashSet::new();
+        for (piece, used) in options {
+            let mut dummy = game.clone();
+            dummy.set_active_piece(piece);
+            let (_, candidates) = Bot::find_placements(&mut dummy);
+
+            for placement in candidates {
+                let below = placement
+                    .abs_locations()
+                    .is_some_and(|cells| cells.iter().all(|p| (p.0 as usize) < height));
+                if !below {
+                    continue;
+                }
+
+                let mut next = game.clone();
+                play(&mut next, &placement);
+                if !seen.insert((next.board.get_arr(), next.hold_piece)) {
+                    continue;
+                }
+
+                placements.push(placement);
+                if next.board.get_mino_count() == 0 {
+                    return true;
+                }
+                // the piece after the known queue can't be planned with
+                if known >= used
+                    && !next.get_game_over()
+                    && self.search(&next, height - next.game_data.last_cleared, known - used, placements)
+                {
+                    return true;
+                }
+                placements.pop();
+            }
+        }
+
+        self.failed.insert(key);
+        false
+    }
+}
+
+// every pocket of empty cells under the all clear line has to be filled by whole pieces
+fn regions_fit(board: &Board, height: usize) -> bool {
+    let height = height.min(BOARD_HEIGHT);
+    let mut visited = [[false; BOARD_WIDTH]; BOARD_HEIGHT];
+    for row in 0..height {
+        for col in 0..BOARD_WIDTH {
+            if visited[row][col] || board.get(row, col) {
+                continue;
+            }
+
+            let mut size = 0usize;
+            let mut stack = vec![(row, col)];
+            visited[row][col] = true;
+            while let Some((r, c)) = stack.pop() {
+                size += 1;
+                let neighbours = [
+                    (r + 1, c),
+                    (r.wrapping_sub(1), c),
+                    (r, c + 1),
+                    (r, c.wrapping_sub(1)),
+                ];
+                for (r, c) in neighbours {
+                    if r < height && c < BOARD_WIDTH && !visited[r][c] && !board.get(r, c) {
+                        visited[r][c] = true;
+                        stack.push((r, c));
+                    }
+                }
+            }
+            if !size.is_multiple_of(4) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod perfect_clear_tests {
+    use super::*;
+    use crate::players::Player;
+
+    fn game_with(active: PieceType, queue: &[PieceType]) -> Game {
+        let mut game = Game::new(Some(1));
+        game.set_active_piece(Piece::new(active));
+        game.piece_queue.set_queue(queue.iter().copied().collect());
+        game
+    }
+
+    fn fill(game: &mut Game, rows: usize, gap: &[usize]) {
+        for row in 0..rows {
+            for col in (0..BOARD_WIDTH).filter(|c| !gap.contains(c)) {
+                game.board.add(row, col);
+            }
+        }
+    }
+
+    #[test]
+    fn test_solve() {
+        // an o fills the last two columns
+        let mut game = game_with(2, &[4, 4, 4, 4, 4]);
+        fill(&mut game, 2, &[8, 9]);
+        let mut solver = PerfectClear::new(PcConfig {
+            max_height: 2,
+            ..Default::default()
+        });
+        let solution = solver.solve(&game).unwrap();
+        assert_eq!(solution.len(), 1);
+        assert_eq!(solution[0].get_type(), 2);
+
+        // with only i pieces it takes four rows, past the limit
+        let mut game = game_with(4, &[4, 4, 4, 4, 4]);
+        fill(&mut game, 2, &[8, 9]);
+        assert!(solver.solve(&game).is_none());
+        assert!(!solver.failed.is_empty());
+    }
+
+    #[test]
+    fn test_hold() {
+        // the o is only reachable through hold
+        let mut game = game_with(4, &[2, 4, 4, 4, 4]);
+        fill(&mut game, 2, &[8, 9]);
+        let solution = PerfectClear::default().solve(&game).unwrap();
+        assert_eq!(solution.len(), 1);
+        assert_eq!(solution[0].get_type(), 2);
+
+        // a pocket of three can never be filled
+        let mut game = game_with(2, &[2, 2, 2, 2, 2]);
+        fill(&mut game, 1, &[0, 1, 2]);
+        assert!(PerfectClear::default().solve(&game).is_none());
+    }
+
+    #[test]
+    fn test_pc_mode() {
+        // five o pieces clear an empty board two rows up
+        let mut bot = Bot::new(game_with(2, &[2, 2, 2, 2, 2, 2]));
+        bot.set_perfect_clear(Some(PcConfig::default()));
+        for _ in 0..5 {
+            assert!(bot.make_move());
+        }
+        assert_eq!(bot.get_game().board.get_mino_count(), 0);
+        assert!(bot.get_game().game_data.all_clear);
+    }
+}
@@ -7,6 +7,7 @@ use crate::constants::types::*;
 use crate::point_vector::*;
 use std::fmt::{Display, Formatter};
 use crate::constants::board_constants::{BOARD_HEIGHT, BOARD_WIDTH};
+use crate::game::game_rules_and_data::KickSet;
 
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Piece {
@@ -28,13 +29,7 @@ impl Display for Piece {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let locations = &PIECE_ROTATIONS[self.piece_type][self.rotation_state];
 
-        let size: i8;
-
-        if self.piece_type == 4 {
-            size = 5;
-        } else {
-            size = 3;
-        }
+        let size: i8 = if self.piece_type == 4 { 5 } else { 3 };
         let half_size = size / 2;
 
         for row in (0..size).rev() {
@@ -46,7 +41,7 @@ impl Display for Piece {
                     write!(f, "□ ")?
                 }
             }
-            write!(f, "\n")?
+            writeln!(f)?
         }
         Ok(())
     }
@@ -81,27 +76,90 @@ impl Piece {
         self.last_kick
     }
 
-    pub fn get_kicks(&self, dir: RotationDirection) -> Vec<PointVector> {
-        let before = (self.rotation_state + NUM_ROTATE_STATES - dir ) % NUM_ROTATE_STATES;
-        let kicks;
+    pub fn get_kicks(&self, dir: RotationDirection, kick_set: &KickSet) -> Vec<PointVector> {
+        let before = (self.rotation_state + NUM_ROTATE_STATES - dir) % NUM_ROTATE_STATES;
+        if self.piece_type == 2 {
+            // O piece is the other special child, it never kicks in any kick set
+            return vec![O_OFFSETS[before][dir - 1]];
+        }
+
+        match kick_set {
+            KickSet::SRSPlus => self.srs_plus_kicks(before, dir),
+            KickSet::SRS => self.srs_kicks(before, dir),
+            KickSet::SRSX => {
+                if dir == 2 {
+                    self.shifted(&SRS_X_180_OFFSETS[before], before)
+                } else {
+                    self.srs_kicks(before, dir)
+                }
+            }
+            KickSet::TetraX => self.shifted(&TETRA_X_OFFSETS, before),
+            KickSet::ASC => match dir {
+                1 => self.shifted(&ASC_OFFSETS, before),
+                3 => {
+                    let mirrored: Vec<PointVector> =
+                        ASC_OFFSETS.iter().map(|kick| PointVector(kick.0, -kick.1)).collect();
+                    self.shifted(&mirrored, before)
+                }
+                _ => self.shifted(&[PointVector(0, 0)], before),
+            },
+            KickSet::NRS => self.shifted(&[self.state_offset(&NRS_STATE_OFFSETS, before)], before),
+            KickSet::ARS => {
+                let offset = self.state_offset(&ARS_STATE_OFFSETS, before);
+                if self.piece_type == 4 {
+                    // the I piece never kicks in ARS
+                    self.shifted(&[offset], before)
+                } else {
+                    let kicks: Vec<PointVector> = ARS_OFFSETS.iter().map(|kick| kick.add(&offset)).collect();
+                    self.shifted(&kicks, before)
+                }
+            }
+            KickSet::None => self.shifted(&[PointVector(0, 0)], before),
+        }
+    }
+
+    fn srs_plus_kicks(&self, before: RotationState, dir: RotationDirection) -> Vec<PointVector> {
         if self.piece_type == 4 {
             // I piece is the special child
             if dir == 2 {
-                kicks = FIVE_180_OFFSETS[before].to_vec()
+                FIVE_180_OFFSETS[before].to_vec()
             } else {
-                kicks = FIVE_OFFSETS[before][dir / 2].to_vec();
+                FIVE_OFFSETS[before][dir / 2].to_vec()
             }
-        } else if self.piece_type == 2 {
-            // O piece is the other special child
-            kicks = vec![O_OFFSETS[before][dir - 1]];
+        } else if dir == 2 {
+            THREE_180_OFFSETS[before].to_vec()
         } else {
-            if dir == 2 {
-                kicks = THREE_180_OFFSETS[before].to_vec()
-            } else {
-                kicks = THREE_OFFSETS[before][dir / 2].to_vec();
-            }
+            THREE_OFFSETS[before][dir / 2].to_vec()
+        }
+    }
+
+    fn srs_kicks(&self, before: RotationState, dir: RotationDirection) -> Vec<PointVector> {
+        if dir == 2 {
+            // plain SRS has no 180 kicks
+            self.shifted(&[PointVector(0, 0)], before)
+        } else if self.piece_type == 4 {
+            SRS_FIVE_OFFSETS[before][dir / 2].to_vec()
+        } else {
+            THREE_OFFSETS[before][dir / 2].to_vec()
         }
-        kicks
+    }
+
+    // moves kicks that are relative to the piece into the I piece's rotation center
+    fn shifted(&self, kicks: &[PointVector], before: RotationState) -> Vec<PointVector> {
+        if self.piece_type != 4 {
+            return kicks.to_vec();
+        }
+        let center = FIVE_CENTERS[self.rotation_state].sub(&FIVE_CENTERS[before]);
+        kicks.iter().map(|kick| kick.add(&center)).collect()
+    }
+
+    fn state_offset(
+        &self,
+        offsets: &[[PointVector; NUM_ROTATE_STATES]; NUM_PIECES],
+        before: RotationState,
+    ) -> PointVector {
+        let offsets = &offsets[self.piece_type];
+        offsets[self.rotation_state].sub(&offsets[before])
     }
 
     pub fn get_rotation(&self) -> RotationState {
@@ -133,18 +191,20 @@ impl Piece {
     }
     // methods
     pub fn new(piece_type: PieceType) -> Self {
+        // a freshly spawned piece has not rotated, so it can't spin
         Self {
             piece_type,
+            last_kick: 999,
             ..Default::default()
         }
     }
 
     // move
     pub fn moved(&mut self, v: PointVector) -> bool {
-        if self.abs_locations() == None {return false}
+        if self.abs_locations().is_none() {return false}
         if let Some(moved) = v.add_to_point(&self.center) {
             for point in self.abs_locations().unwrap(){
-                if v.add_to_point(&point) == None {
+                if v.add_to_point(&point).is_none() {
                     return false
                 }
             }
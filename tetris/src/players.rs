@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use crate::communications::Suggestion;
+use crate::protocol::{Candidate, Suggestion};
 use crate::constants::bot_constants::*;
 use crate::constants::types::*;
 use crate::game::Game;
@@ -17,9 +17,13 @@ pub trait Player {
         let action = self.get_next_move();
         // println!("{:?}", action);
         do_move_list(self.get_game_mut(), action);
+        self.move_played();
         true
     }
 
+    // the move from get_next_move has been played, asking for a move alone changes nothing
+    fn move_played(&mut self) {}
+
     fn make_n_moves(&mut self, n: usize) {
         for _ in 0..n {
             if !self.make_move() {
@@ -30,21 +34,30 @@ pub trait Player {
 
     fn make_suggest_move(&mut self) -> Suggestion {
         if self.get_game().get_game_over() {
-            return Suggestion {
-                input_list: Vec::new(),
-                info: "bot died".to_string(),
-            };
+            return Suggestion::new(Vec::new(), "bot died".to_string());
         }
+        let before = self.get_game().clone();
         let action = self.get_next_move();
         let out = Suggestion {
             input_list: Self::command_list_string(&action),
-            info: "".to_string(),
+            info: self.suggestion_info(),
+            candidates: self.suggestion_candidates(&before),
         };
         // println!("{:?}", action);
         do_move_list(self.get_game_mut(), action);
+        self.move_played();
         out
     }
 
+    // what the last get_next_move weighed up, from the game it was asked about
+    fn suggestion_candidates(&self, _game: &Game) -> Vec<Candidate> {
+        Vec::new()
+    }
+
+    fn suggestion_info(&self) -> String {
+        String::new()
+    }
+
     fn command_list_string(commands: &CommandList) -> Vec<String> {
         commands
             .iter()
@@ -65,15 +78,31 @@ pub fn do_command(game: &mut Game, command: Command) -> bool {
         Command::None => true,
         Command::MoveLeft => game.active_left(),
         Command::MoveRight => game.active_right(),
-        Command::SoftDrop => game.active_drop(),
+        Command::DasLeft => game.active_das_left(),
+        Command::DasRight => game.active_das_right(),
+        Command::SoftDrop => {
+            if game.active_soft_drop() {
+                true
+            } else if !game.get_game_rules().allow_hard_drop {
+                // soft dropping a grounded piece waits out the lock delay
+                let game_over = !game.set_piece();
+                game.set_game_over(game_over);
+                true
+            } else {
+                false
+            }
+        }
         Command::RotateCW => game.active_cw(),
         Command::RotateCCW => game.active_ccw(),
-        Command::Rotate180 => game.active_180(),
+        Command::Rotate180 => game.get_game_rules().allow_180 && game.active_180(),
         Command::Hold => {
             game.hold();
             true
         }
         Command::HardDrop => {
+            if !game.get_game_rules().allow_hard_drop {
+                return false;
+            }
             let game_over = !game.hard_drop();
             game.set_game_over(game_over);
             true
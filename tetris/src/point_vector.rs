@@ -47,4 +47,12 @@ impl PointVector {
     pub fn negative(&self) -> Self {
         Self(-self.0, -self.1)
     }
+
+    pub fn add(&self, other: &Self) -> Self {
+        Self(self.0 + other.0, self.1 + other.1)
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.negative())
+    }
 }
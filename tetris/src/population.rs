@@ -1 +1,417 @@
+#![allow(dead_code)]
 
+use crate::bot::Bot;
+use crate::constants::board_constants::BOARD_WIDTH;
+use crate::game::Game;
+use crate::players::Player;
+use crate::weight::Weights;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// every weight is treated as a polynomial of this many coefficients
+pub const GENOME_DEGREE: usize = 3;
+
+const SPRINT_LINES: usize = 40;
+const GARBAGE_INTERVAL: usize = 4;
+const GARBAGE_LINES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Fitness {
+    // fewest pieces to clear 40 lines
+    Sprint,
+    // most pieces placed while garbage keeps coming in
+    Survival,
+    // most attack per piece
+    Efficiency,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PopulationConfig {
+    pub size: usize,
+    pub elites: usize,
+    pub tournament_size: usize,
+    pub crossover_rate: f32,
+    pub mutation_rate: f32,
+    pub mutation_strength: f32,
+    pub fitness: Fitness,
+    pub seeds: Vec<usize>,
+    pub max_pieces: usize,
+    pub threads: usize,
+}
+
+impl Default for PopulationConfig {
+    fn default() -> Self {
+        Self {
+            size: 24,
+            elites: 2,
+            tournament_size: 3,
+            crossover_rate: 0.7,
+            mutation_rate: 0.2,
+            mutation_strength: 0.2,
+            fitness: Fitness::Survival,
+            seeds: vec![1, 2, 3],
+            max_pieces: 300,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Individual {
+    pub genome: Vec<Vec<f32>>,
+    pub fitness: Option<f32>,
+}
+
+impl Individual {
+    pub fn from_weights(weights: &Weights) -> Self {
+        let genome = weights
+            .coefficients()
+            .into_iter()
+            .map(|mut coefficients| {
+                // pad short polynomials, longer ones keep every term
+                coefficients.resize(coefficients.len().max(GENOME_DEGREE), 0.0);
+                coefficients
+            })
+            .collect();
+
+        Self {
+            genome,
+            fitness: None,
+        }
+    }
+
+    pub fn weights(&self) -> Weights {
+        Weights::from_coefficients(&self.genome)
+    }
+
+    // unevaluated and nan fitness rank below everything
+    fn rank(&self) -> f32 {
+        self.fitness.filter(|f| !f.is_nan()).unwrap_or(f32::NEG_INFINITY)
+    }
+}
+
+// how a generation did once evaluated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best: f32,
+    pub mean: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Population {
+    pub config: PopulationConfig,
+    pub generation: usize,
+    pub individuals: Vec<Individual>,
+    seed: u64,
+}
+
+impl Population {
+    // init
+    pub fn new(config: PopulationConfig, base: &Weights, seed: u64) -> Self {
+        let mut out = Self {
+            config,
+            generation: 0,
+            individuals: Vec::new(),
+            seed,
+        };
+
+        // the starting weights are kept as is, everything else is a mutation of them
+        let original = Individual::from_weights(base);
+        let mut rng = out.rng(0);
+        out.individuals.push(original.clone());
+        while out.individuals.len() < out.config.size {
+            let mut individual = original.clone();
+            out.mutate(&mut individual, &mut rng, 1.0);
+            out.individuals.push(individual);
+        }
+        out
+    }
+
+    // checkpoints
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    // evolution
+    // the stats of every generation evaluated, the last being the one left at the end
+    pub fn evolve(&mut self, generations: usize, checkpoint: Option<&Path>) -> io::Result<Vec<GenerationStats>> {
+        let mut out = Vec::new();
+        for _ in 0..generations {
+            self.evaluate();
+            out.push(self.stats());
+            if let Some(path) = checkpoint {
+                self.save(path)?;
+            }
+            self.next_generation();
+        }
+        self.evaluate();
+        out.push(self.stats());
+        if let Some(path) = checkpoint {
+            self.save(path)?;
+        }
+        Ok(out)
+    }
+
+    pub fn stats(&self) -> GenerationStats {
+        let fitness: Vec<f32> = self.individuals.iter().filter_map(|i| i.fitness).collect();
+        GenerationStats {
+            generation: self.generation,
+            best: self.best().fitness.unwrap_or(f32::NEG_INFINITY),
+            mean: fitness.iter().sum::<f32>() / fitness.len().max(1) as f32,
+        }
+    }
+
+    pub fn best(&self) -> &Individual {
+        self.individuals
+            .iter()
+            .max_by(|a, b| a.rank().total_cmp(&b.rank()))
+            .unwrap()
+    }
+
+    fn rng(&self, generation: usize) -> StdRng {
+        // seeded by the generation being made so a resumed run makes the same choices
+        StdRng::seed_from_u64(self.seed.wrapping_add(generation as u64))
+    }
+
+    fn next_generation(&mut self) {
+        let mut rng = self.rng(self.generation + 1);
+        let mut sorted = self.individuals.clone();
+        sorted.sort_by(|a, b| b.rank().total_cmp(&a.rank()));
+
+        // elites carry over untouched, along with their fitness
+        let mut next: Vec<Individual> = sorted.iter().take(self.config.elites).cloned().collect();
+
+        while next.len() < self.config.size {
+            let mut child = self.select(&mut rng).clone();
+            if rng.gen::<f32>() < self.config.crossover_rate {
+                let other = self.select(&mut rng).clone();
+                child = Population::crossover(&child, &other, &mut rng);
+            }
+            self.mutate(&mut child, &mut rng, self.config.mutation_rate);
+            child.fitness = None;
+            next.push(child);
+        }
+
+        self.individuals = next;
+        self.generation += 1;
+    }
+
+    fn select(&self, rng: &mut StdRng) -> &Individual {
+        (0..self.config.tournament_size.max(1))
+            .map(|_| &self.individuals[rng.gen_range(0..self.individuals.len())])
+            .max_by(|a, b| a.rank().total_cmp(&b.rank()))
+            .unwrap()
+    }
+
+    fn crossover(a: &Individual, b: &Individual, rng: &mut StdRng) -> Individual {
+        // each coefficient is a random blend of the two parents
+        let genome = a
+            .genome
+            .iter()
+            .zip(&b.genome)
+            .map(|(a, b)| {
+                a.iter()
+                    .zip(b)
+                    .map(|(a, b)| {
+                        let t: f32 = rng.gen();
+                        a * t + b * (1.0 - t)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Individual {
+            genome,
+            fitness: None,
+        }
+    }
+
+    fn mutate(&self, individual: &mut Individual, rng: &mut StdRng, rate: f32) {
+        // gaussian noise relative to the size of the coefficient, zeros get nudged too
+        for coefficient in individual.genome.iter_mut().flatten() {
+            if rng.gen::<f32>() < rate {
+                let scale = coefficient.abs().max(1.0) * self.config.mutation_strength;
+                *coefficient += scale * gaussian(rng);
+            }
+        }
+    }
+
+    // fitness
+    fn evaluate(&mut self) {
+        let pending: Vec<usize> = (0..self.individuals.len())
+            .filter(|&i| self.individuals[i].fitness.is_none())
+            .collect();
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for _ in 0..self.config.threads.max(1) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= pending.len() {
+                        break;
+                    }
+                    let individual = pending[index];
+                    let fitness = self.fitness(&self.individuals[individual].weights());
+                    results.lock().unwrap().push((individual, fitness));
+                });
+            }
+        });
+
+        for (individual, fitness) in results.into_inner().unwrap() {
+            self.individuals[individual].fitness = Some(fitness);
+        }
+    }
+
+    fn fitness(&self, weights: &Weights) -> f32 {
+        let total: f32 = self
+            .config
+            .seeds
+            .iter()
+            .map(|&seed| self.play(weights, seed))
+            .sum();
+        total / self.config.seeds.len().max(1) as f32
+    }
+
+    fn play(&self, weights: &Weights, seed: usize) -> f32 {
+        let mut bot = Bot::from_weights(Game::new(Some(seed)), weights.clone());
+        let mut garbage_rng = StdRng::seed_from_u64(seed as u64);
+        let max_pieces = self.config.max_pieces;
+
+        while bot.get_game().game_data.pieces_placed < max_pieces {
+            if self.config.fitness == Fitness::Sprint
+                && bot.get_game().game_data.lines_cleared >= SPRINT_LINES
+            {
+                break;
+            }
+            if self.config.fitness == Fitness::Survival
+                && bot.get_game().game_data.pieces_placed % GARBAGE_INTERVAL == GARBAGE_INTERVAL - 1
+            {
+                let hole = garbage_rng.gen_range(0..BOARD_WIDTH);
+                bot.get_game_mut().receive_garbage(GARBAGE_LINES, hole);
+            }
+            if !bot.make_move() || bot.get_game().get_game_over() {
+                break;
+            }
+        }
+
+        let data = &bot.get_game().game_data;
+        let died = bot.get_game().get_game_over();
+        match self.config.fitness {
+            Fitness::Sprint => {
+                // unfinished sprints rank behind every finished one, by lines cleared
+                if data.lines_cleared >= SPRINT_LINES {
+                    -(data.pieces_placed as f32)
+                } else {
+                    data.lines_cleared as f32 - (2 * max_pieces) as f32
+                }
+            }
+            Fitness::Survival => data.pieces_placed as f32,
+            Fitness::Efficiency => {
+                let app = data.lines_sent as f32 / data.pieces_placed.max(1) as f32;
+                if died {
+                    app * data.pieces_placed as f32 / max_pieces as f32
+                } else {
+                    app
+                }
+            }
+        }
+    }
+}
+
+fn gaussian(rng: &mut StdRng) -> f32 {
+    // box-muller
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+#[cfg(test)]
+mod population_tests {
+    use super::*;
+
+    #[test]
+    fn test_genome() {
+        let weights = Weights::default();
+        let individual = Individual::from_weights(&weights);
+        assert_eq!(individual.genome.len(), 12);
+        assert!(individual.genome.iter().all(|g| g.len() == GENOME_DEGREE));
+        assert_eq!(individual.weights().coefficients(), weights.coefficients());
+
+        // longer polynomials aren't cut down to the genome degree
+        let mut coefficients = weights.coefficients();
+        coefficients[0] = vec![1.0, 2.0, 3.0, 4.0];
+        let individual = Individual::from_weights(&Weights::from_coefficients(&coefficients));
+        assert_eq!(individual.genome[0], vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_generation() {
+        let config = PopulationConfig {
+            size: 6,
+            elites: 2,
+            ..Default::default()
+        };
+        let mut population = Population::new(config, &Weights::default(), 3);
+        assert_eq!(population.individuals.len(), 6);
+        for (i, individual) in population.individuals.iter_mut().enumerate() {
+            individual.fitness = Some(i as f32);
+        }
+
+        let best = population.best().genome.clone();
+        assert_eq!(population.stats(), GenerationStats { generation: 0, best: 5.0, mean: 2.5 });
+        population.next_generation();
+        assert_eq!(population.generation, 1);
+        assert_eq!(population.individuals.len(), 6);
+        // elites survive with their fitness, children need evaluating
+        assert_eq!(population.individuals[0].genome, best);
+        assert_eq!(population.individuals[1].fitness, Some(4.0));
+        assert!(population.individuals[2..].iter().all(|i| i.fitness.is_none()));
+
+        // nan fitness ranks last instead of panicking
+        population.individuals[0].fitness = Some(f32::NAN);
+        population.individuals[1].fitness = Some(1.0);
+        assert_eq!(population.best().fitness, Some(1.0));
+        population.next_generation();
+        assert_eq!(population.individuals[0].fitness, Some(1.0));
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let population = Population::new(Default::default(), &Weights::default(), 7);
+        let path = std::env::temp_dir().join(format!("ivfish_population_test_{}.json", std::process::id()));
+        population.save(&path).unwrap();
+        let loaded = Population::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.generation, population.generation);
+        assert_eq!(loaded.individuals.len(), population.individuals.len());
+        assert_eq!(loaded.individuals[3].genome, population.individuals[3].genome);
+
+        // resumed runs pick the same children
+        let mut a = population.clone();
+        let mut b = loaded;
+        for population in [&mut a, &mut b] {
+            for individual in population.individuals.iter_mut() {
+                individual.fitness = Some(individual.genome[0][1]);
+            }
+            population.next_generation();
+        }
+        assert_eq!(a.individuals[5].genome, b.individuals[5].genome);
+    }
+}
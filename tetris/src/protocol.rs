@@ -0,0 +1,236 @@
+#![allow(dead_code)]
+
+// the bot server's messages, one json object per websocket text frame, tagged by "type"
+//
+// the client opens with a hello carrying PROTOCOL_VERSION, and the server answers with its own
+// hello, or an error and a closed connection if the versions differ. after that the client sends
+// rules once per game and a state whenever the bot is to move, and gets a suggestion back.
+// fields added later have to be optional so that older peers keep working within a version.
+//
+// version 1 breaks the unversioned protocol before it: the hello is required, so clients that
+// start with rules are refused, and the state message was called play.
+use crate::constants::types::*;
+use crate::piece::Piece;
+use serde::{Deserialize, Serialize};
+
+// bumped whenever a message changes in a way an older peer would misread
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// the messages this server understands beyond the handshake
+pub const CAPABILITIES: [&str; 5] = ["rules", "state", "suggestion", "stop", "stats"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientMessage {
+    Hello {
+        version: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    Rules(RulesMessage),
+    // the game as the client sees it, asking for a suggestion
+    State(StateMessage),
+    // a suggestion for the game as the bot has it
+    Start,
+    Stop,
+    Stats,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerMessage {
+    Hello {
+        version: u32,
+        capabilities: Vec<String>,
+        name: String,
+    },
+    Suggestion(Suggestion),
+    Error {
+        reason: String,
+        message: String,
+    },
+    Stats(Stats),
+}
+
+impl ServerMessage {
+    pub fn hello() -> Self {
+        ServerMessage::Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+            name: format!("IVFISH {}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+// tetr.io's room options, anything missing falls back to the defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RulesMessage {
+    pub seed: u64,
+    pub bagtype: Option<String>,
+    pub allow_harddrop: Option<bool>,
+    pub allow180: bool,
+    pub b2bchaining: Option<bool>,
+    // tetr.io charges a surge whenever it doesn't chain unless told otherwise
+    pub b2bcharging: Option<bool>,
+    pub boardheight: usize,
+    pub kickset: String,
+    pub spinbonuses: Option<String>,
+    pub combotable: Option<String>,
+    pub garbagemultiplier: Option<f32>,
+    pub garbageincrease: Option<f32>,
+    // sent in frames
+    pub garbagemargin: Option<f32>,
+    pub allclear_garbage: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateMessage {
+    // top row first
+    pub board: Vec<Vec<bool>>,
+    pub current: TetrioPiece,
+    pub queue: Vec<TetrioPiece>,
+    #[serde(default)]
+    pub hold: Option<TetrioPiece>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub input_list: Vec<String>,
+    // what came up with the move
+    pub info: String,
+    // the best placements the bot weighed, best first, the first being the one suggested
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+}
+
+impl Suggestion {
+    pub fn new(input_list: Vec<String>, info: String) -> Self {
+        Self {
+            input_list,
+            info,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+    pub input_list: Vec<String>,
+    pub placement: Placement,
+    // the placements the search plans to follow it with
+    pub follow_up: Vec<Placement>,
+    // lower is better for both
+    pub board_score: Score,
+    pub versus_score: Score,
+    // lines sent by the placement, and by it and the follow up together
+    pub attack: u16,
+    pub planned_attack: u16,
+}
+
+// where a piece ends up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    pub piece: TetrioPiece,
+    pub rotation: RotationState,
+    // [row, col] with row 0 at the bottom
+    pub cells: Vec<[i8; 2]>,
+}
+
+impl From<&Piece> for Placement {
+    fn from(piece: &Piece) -> Self {
+        Self {
+            piece: TetrioPiece::from_type(piece.get_type()),
+            rotation: piece.get_rotation_state(),
+            cells: piece.abs_locations().unwrap_or_default().iter().map(|p| [p.0, p.1]).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub pieces_placed: usize,
+    pub lines_cleared: usize,
+    pub lines_sent: u16,
+    pub combo: i8,
+    pub b2b: i8,
+    pub suggestions: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TetrioPiece {
+    Z,
+    L,
+    O,
+    S,
+    I,
+    J,
+    T,
+}
+
+impl TetrioPiece {
+    // the same order as piece types
+    const ALL: [TetrioPiece; 7] = [
+        TetrioPiece::Z,
+        TetrioPiece::L,
+        TetrioPiece::O,
+        TetrioPiece::S,
+        TetrioPiece::I,
+        TetrioPiece::J,
+        TetrioPiece::T,
+    ];
+
+    pub fn from_type(piece_type: PieceType) -> Self {
+        TetrioPiece::ALL[piece_type]
+    }
+
+    pub fn get_type(self) -> PieceType {
+        self as PieceType
+    }
+}
+
+#[cfg(test)]
+mod protocol_tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let hello: ClientMessage = serde_json::from_str(r#"{"type":"hello","version":1}"#).unwrap();
+        assert_eq!(
+            hello,
+            ClientMessage::Hello {
+                version: 1,
+                capabilities: Vec::new()
+            }
+        );
+
+        let state = r#"{"type":"state","board":[],"current":"t","queue":["i","o"],"hold":null}"#;
+        let ClientMessage::State(state) = serde_json::from_str(state).unwrap() else {
+            panic!("not a state");
+        };
+        assert_eq!(state.queue, vec![TetrioPiece::I, TetrioPiece::O]);
+        assert_eq!(serde_json::to_value(ClientMessage::State(state)).unwrap()["type"], "state");
+        // the unversioned protocol's name for it is gone
+        let old = r#"{"type":"play","board":[],"current":"t","queue":[],"hold":null}"#;
+        assert!(serde_json::from_str::<ClientMessage>(old).is_err());
+
+        let suggestion = ServerMessage::Suggestion(Suggestion::new(vec!["hardDrop".to_string()], String::new()));
+        let value = serde_json::to_value(&suggestion).unwrap();
+        assert_eq!(value["type"], "suggestion");
+        assert_eq!(value["input_list"][0], "hardDrop");
+        assert_eq!(serde_json::from_value::<ServerMessage>(value).unwrap(), suggestion);
+        // suggestions from before candidates were added still read
+        let old = r#"{"type":"suggestion","input_list":[],"info":""}"#;
+        assert_eq!(serde_json::from_str::<ServerMessage>(old).unwrap(), ServerMessage::Suggestion(Suggestion::new(Vec::new(), String::new())));
+
+        let placement = Placement::from(&Piece::new(6));
+        assert_eq!(placement.piece, TetrioPiece::T);
+        assert_eq!(placement.cells.len(), 4);
+
+        let ServerMessage::Hello { version, capabilities, .. } = ServerMessage::hello() else {
+            panic!("not a hello");
+        };
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert!(capabilities.contains(&"stats".to_string()));
+    }
+}
@@ -1,7 +1,9 @@
 #![allow(dead_code)]
 
+use crate::constants::piece_constants::NUM_PIECES;
 use crate::constants::queue_constants::*;
 use crate::constants::types::*;
+use crate::constants::hash_constants::*;
 use crate::piece::Piece;
 use rand::Rng;
 use std::collections::VecDeque;
@@ -14,19 +16,23 @@ pub struct PieceQueue {
     queue: VecDeque<PieceType>,
     randomizer: BagType,
     seed: usize,
+    // classic rerolls a repeat of the last piece once
+    last_generated: Option<PieceType>,
 }
 
 impl PieceQueue {
     pub fn new(optional_seed: Option<usize>) -> Self {
-        Self {
-            seed: optional_seed.unwrap_or_else(|| rand::thread_rng().gen_range(0..MODULUS - 1)),
-            ..Default::default()
-        }
+        Self::new_alt_randomizer(optional_seed, BagType::default())
     }
 
     pub fn new_alt_randomizer(optional_seed: Option<usize>, randomizer: BagType) -> Self {
+        let seed = optional_seed.unwrap_or_else(|| rand::thread_rng().gen_range(0..MODULUS - 1));
         Self {
-            seed: optional_seed.unwrap_or_else(|| rand::thread_rng().gen_range(0..MODULUS - 1)),
+            // the same wrap as tetr.io, a seed of 0 would never move
+            seed: match seed % MODULUS {
+                0 => MODULUS - 1,
+                seed => seed,
+            },
             randomizer,
             ..Default::default()
         }
@@ -46,16 +52,22 @@ impl PieceQueue {
         &self.queue
     }
 
+    pub fn state_hash(&self) -> u64 {
+        // the seed moves on with every bag, and the length says how far into it we are
+        mix(QUEUE_KEY ^ self.seed as u64 ^ ((self.queue.len() as u64) << 40))
+    }
+
     pub fn get_vec(&self) -> Vec<PieceType> {
         let mut out: Vec<PieceType> = Vec::new();
-        for p in self.get_queue().into_iter() {
+        for p in self.get_queue().iter() {
             out.push(*p);
         }
         out
     }
 
     pub fn next(&mut self) -> Piece {
-        if self.queue.len() <= MIN_QUEUE_LENGTH {
+        // classic and total mayhem only add one piece at a time
+        while self.queue.len() <= MIN_QUEUE_LENGTH {
             self.next_bag();
         }
 
@@ -82,30 +94,48 @@ impl PieceQueue {
     }
 
     fn fourteen_bag(&mut self) {
-        let arr = self.shuffle_fourteen([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]);
+        let arr = self.shuffle_fourteen([0, 1, 2, 3, 4, 5, 6, 0, 1, 2, 3, 4, 5, 6]);
         self.queue.extend(arr.iter());
     }
 
     fn classic(&mut self) {
-        unimplemented!()
+        // one extra roll that always rerolls, and a repeat of the last piece rerolls too
+        let mut index = (self.next_num() * (NUM_PIECES as f64 + 1.0)) as usize;
+        if Some(index) == self.last_generated || index >= NUM_PIECES {
+            index = (self.next_num() * NUM_PIECES as f64) as usize;
+        }
+        self.last_generated = Some(index);
+        self.queue.push_back(index);
     }
 
     fn pairs(&mut self) {
-        unimplemented!()
+        // three each of the first two pieces of a shuffled bag
+        let bag = self.shuffle_seven([0, 1, 2, 3, 4, 5, 6]);
+        let arr = self.shuffle_six([bag[0], bag[0], bag[0], bag[1], bag[1], bag[1]]);
+        self.queue.extend(arr.iter());
     }
 
     fn total_mayhem(&mut self) {
-        unimplemented!()
+        let piece = (self.next_num() * NUM_PIECES as f64) as usize;
+        self.queue.push_back(piece);
     }
 
-    fn next_num(&mut self) -> f32 {
+    fn next_num(&mut self) -> f64 {
         self.seed = self.seed * MULTIPLIER % MODULUS;
-        (self.seed - 1) as f32 / MODULUS as f32
+        (self.seed - 1) as f64 / (MODULUS - 1) as f64
+    }
+
+    fn shuffle_six(&mut self, mut arr: [PieceType; 6]) -> [PieceType; 6] {
+        for i in (1..6).rev() {
+            let r = (self.next_num() * (i as f64 + 1.0)) as usize;
+            (arr[i], arr[r]) = (arr[r], arr[i])
+        }
+        arr
     }
 
     fn shuffle_seven(&mut self, mut arr: [PieceType; 7]) -> [PieceType; 7] {
         for i in (1..7).rev() {
-            let r = (self.next_num() * (i as f32 + 1.0)) as usize;
+            let r = (self.next_num() * (i as f64 + 1.0)) as usize;
             (arr[i], arr[r]) = (arr[r], arr[i])
         }
         arr
@@ -113,7 +143,7 @@ impl PieceQueue {
 
     fn shuffle_fourteen(&mut self, mut arr: [PieceType; 14]) -> [PieceType; 14] {
         for i in (1..14).rev() {
-            let r = (self.next_num() * (i as f32 + 1.0)) as usize;
+            let r = (self.next_num() * (i as f64 + 1.0)) as usize;
             (arr[i], arr[r]) = (arr[r], arr[i])
         }
         arr
@@ -127,15 +157,16 @@ pub fn piece_type_to_string(piece: PieceType) -> String {
 
 impl Display for PieceQueue {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for piece in 0..MIN_QUEUE_LENGTH {
-            write!(f, "{} ", piece_type_to_string(self.queue[piece]))?;
+        for &piece in self.queue.iter().take(MIN_QUEUE_LENGTH) {
+            write!(f, "{} ", piece_type_to_string(piece))?;
         }
         Ok(())
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Default)]
 pub enum BagType {
+    #[default]
     SevenBag,
     FourteenBag,
     Classic,
@@ -143,12 +174,6 @@ pub enum BagType {
     Mayhem,
 }
 
-impl Default for BagType {
-    fn default() -> Self {
-        Self::SevenBag
-    }
-}
-
 impl FromStr for BagType {
     type Err = ParseError;
 
@@ -206,4 +231,30 @@ mod piece_queue_tests {
             assert_eq!(queue.next(), Piece::new(piece));
         }
     }
+
+    fn assert_sequence(randomizer: BagType, seed: usize, expected: [PieceType; 14]) {
+        let mut queue = PieceQueue::new_alt_randomizer(Some(seed), randomizer);
+        for piece in expected {
+            assert_eq!(queue.next(), Piece::new(piece));
+        }
+    }
+
+    #[test]
+    fn test_other_randomizers() {
+        // reference sequences from a port of tetr.io's randomizer, which agrees with osk on 7-bag
+        // ZILSTTLJOSOIJZ
+        assert_sequence(BagType::FourteenBag, 15, [0, 4, 1, 3, 6, 6, 1, 5, 2, 3, 2, 4, 5, 0]);
+        // ZOTOJLZTSITZJZ
+        assert_sequence(BagType::Classic, 15, [0, 2, 6, 2, 5, 1, 0, 6, 3, 4, 6, 0, 5, 0]);
+        // ZTLIOJOZJIJZLZ
+        assert_sequence(BagType::Classic, 7000, [0, 6, 1, 4, 2, 5, 2, 0, 5, 4, 5, 0, 1, 0]);
+        // IITTIT LLZZZL ZO
+        assert_sequence(BagType::Pairs, 15, [4, 4, 6, 6, 4, 6, 1, 1, 0, 0, 0, 1, 0, 2]);
+        // SSTSTT LLLSSS LI
+        assert_sequence(BagType::Pairs, 7000, [3, 3, 6, 3, 6, 6, 1, 1, 1, 3, 3, 3, 1, 4]);
+        // ZTOTTLILLZJJSS
+        assert_sequence(BagType::Mayhem, 15, [0, 6, 2, 6, 6, 1, 4, 1, 1, 0, 5, 5, 3, 3]);
+        // ZJLSOJOZZJSTJZ
+        assert_sequence(BagType::Mayhem, 7000, [0, 5, 1, 3, 2, 5, 2, 0, 0, 5, 3, 6, 5, 0]);
+    }
 }
@@ -0,0 +1,488 @@
+#![allow(dead_code)]
+
+use crate::bot::{Bot, Budget, SearchConfig};
+use crate::constants::types::*;
+use crate::game::Game;
+use crate::piece::Piece;
+use crate::weight::Weights;
+use itertools::{izip, Itertools};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::collections::{HashSet, VecDeque};
+use std::iter::zip;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+// when to stop growing the tree
+pub struct Limits<'a> {
+    start: Instant,
+    budget: Budget,
+    stop: Option<&'a AtomicBool>,
+}
+
+impl<'a> Limits<'a> {
+    pub fn new(budget: Budget, stop: Option<&'a AtomicBool>) -> Self {
+        Self {
+            start: Instant::now(),
+            budget,
+            stop,
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    fn exhausted(&self, nodes: usize) -> bool {
+        self.stopped() || self.budget.exhausted(self.start, nodes)
+    }
+
+    fn out_of_time(&self) -> bool {
+        self.stopped() || self.budget.out_of_time(self.start)
+    }
+}
+
+// the beam search from one game, kept between moves so finished work isn't thrown away
+#[derive(Clone)]
+pub struct SearchTree {
+    game: Game,
+    depth: usize,
+    // the deepest finished level
+    moves: MoveList,
+    placements: Vec<PlacementList>,
+    scores: ScoreList,
+    // boards already scored, so a board reached again is scored once
+    board_scores: ScoreCache,
+}
+
+impl SearchTree {
+    // init
+    pub fn new(game: &Game, weights: &Weights) -> Self {
+        let mut dummy = game.clone();
+        let mut board_scores = ScoreCache::new();
+        let (moves, placements, scores) =
+            Bot::move_placement_score_cached(&mut dummy, weights, &ScoreCache::new(), &mut board_scores);
+
+        Self {
+            game: game.clone(),
+            depth: 1,
+            moves,
+            placements: placements.into_iter().map(|x| vec![x]).collect(),
+            scores,
+            board_scores,
+        }
+    }
+
+    // getters
+    pub fn get_game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn frontier(&self) -> (&MoveList, &[PlacementList], &ScoreList) {
+        (&self.moves, &self.placements, &self.scores)
+    }
+
+    pub fn into_frontier(self) -> (MoveList, Vec<PlacementList>, ScoreList) {
+        (self.moves, self.placements, self.scores)
+    }
+
+    pub fn best(&self) -> Option<(&CommandList, &PlacementList, Score)> {
+        izip!(&self.moves, &self.placements, &self.scores)
+            .map(|(moves, placements, (board, versus))| (moves, placements, board + versus))
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+    }
+
+    // the best line for each of the n best first placements, best first
+    pub fn ranked(&self, n: usize) -> Vec<(&CommandList, &PlacementList, (Score, Score))> {
+        let total = |i: usize| self.scores[i].0 + self.scores[i].1;
+        let order = (0..self.scores.len()).sorted_by(|&a, &b| total(a).partial_cmp(&total(b)).unwrap());
+
+        let mut out: Vec<(&CommandList, &PlacementList, (Score, Score))> = Vec::new();
+        for index in order {
+            if out.len() >= n {
+                break;
+            }
+            if out.iter().any(|(_, placements, _)| placements[0] == self.placements[index][0]) {
+                continue;
+            }
+            out.push((&self.moves[index], &self.placements[index], self.scores[index]));
+        }
+        out
+    }
+
+    // search
+    pub fn grow(
+        &mut self,
+        search: &SearchConfig,
+        weights: &Weights,
+        limits: &Limits,
+        pool: Option<&ThreadPool>,
+    ) -> bool {
+        let mut nodes = 0;
+        while self.depth < search.max_depth {
+            if !self.grow_level(search, weights, limits, pool, &mut nodes) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn grow_level(
+        &mut self,
+        search: &SearchConfig,
+        weights: &Weights,
+        limits: &Limits,
+        pool: Option<&ThreadPool>,
+        nodes: &mut usize,
+    ) -> bool {
+        let curr_depth = self.depth;
+        let limit = if curr_depth.is_multiple_of(search.prune_depth) { search.beam_width } else { usize::MAX };
+
+        //TODO! use multiple scoring functions
+        let combined_scores: Vec<Score> = self.scores.iter().map(|(board, versus)| board + versus).collect();
+        let order = (0..combined_scores.len())
+            .sorted_by(|&i1, &i2| combined_scores[i1].partial_cmp(&combined_scores[i2]).unwrap());
+
+        //choosing nodes to expand, best first so the best path to a state is the one kept,
+        //duplicates are skipped before counting so the beam is filled with distinct states.
+        //this only dedupes within a level, the hash counts the pieces used so two levels never share a state
+        let mut selected = Vec::new();
+        let mut hashes = HashSet::new();
+        for index in order {
+            if selected.len() >= limit {
+                break;
+            }
+            let mut dummy = self.game.clone();
+            for p in &self.placements[index] {
+                play(&mut dummy, p);
+            }
+
+            let hash = dummy.state_hash();
+            if !hashes.insert(hash) {
+                continue;
+            }
+            // out of budget, the last finished depth is the best we have
+            if limits.exhausted(*nodes) {
+                return false;
+            }
+            *nodes += 1;
+            selected.push((index, dummy));
+        }
+
+        //generating next_mps, expansions are independent so they can run on every thread
+        //boards scored in this level are only shared once it's done, the threads just read the cache
+        let known = &self.board_scores;
+        let expand = |(index, mut dummy): (usize, Game)| {
+            if limits.out_of_time() {
+                return None;
+            }
+            let mut fresh = ScoreCache::new();
+            let (_, add_placements, add_scores) =
+                Bot::move_placement_score_cached(&mut dummy, weights, known, &mut fresh);
+            Some((index, add_placements, add_scores, fresh))
+        };
+        let expansions: Option<Vec<_>> = match pool {
+            Some(pool) => pool.install(|| selected.into_par_iter().map(expand).collect()),
+            None => selected.into_iter().map(expand).collect(),
+        };
+        let Some(expansions) = expansions else {
+            return false;
+        };
+
+        // collected in order, so the result doesn't depend on the thread count
+        let mut next_moves = MoveList::new();
+        let mut next_placements = Vec::new();
+        let mut next_scores = ScoreList::new();
+        let discount = 1.0 - (search.discount * curr_depth as f32 / search.max_depth as f32);
+        for (index, add_placements, add_scores, fresh) in expansions {
+            self.board_scores.extend(fresh);
+            let versus = self.scores[index].1;
+            for (add_place, (board, add_versus)) in zip(add_placements, add_scores) {
+                let mut placements = self.placements[index].clone();
+                placements.push(add_place);
+
+                next_moves.push(self.moves[index].clone());
+                next_placements.push(placements);
+                next_scores.push((board, (versus + add_versus) * discount));
+            }
+        }
+
+        self.moves = next_moves;
+        self.placements = next_placements;
+        self.scores = next_scores;
+        self.depth += 1;
+        true
+    }
+
+    // moves the root past a placement, keeping only the lines that started with it
+    pub fn advance(&mut self, placement: &Piece) -> bool {
+        play(&mut self.game, placement);
+
+        // the inputs for the new first placements, lines that can't be reached from here are dropped
+        let (paths, reachable) = Bot::find_paths(&self.game);
+        let placements = mem::take(&mut self.placements);
+        let scores = mem::take(&mut self.scores);
+        self.moves.clear();
+        for (placements, score) in zip(placements, scores) {
+            if placements.len() < 2 || &placements[0] != placement {
+                continue;
+            }
+            let Some(index) = reachable.iter().position(|p| p == &placements[1]) else {
+                continue;
+            };
+            self.moves.push(paths[index].clone());
+            self.placements.push(placements[1..].to_vec());
+            self.scores.push(score);
+        }
+        self.depth -= 1;
+        // most boards scored from the old root can't come up again, don't let them pile up
+        self.board_scores.clear();
+        !self.placements.is_empty()
+    }
+
+    // lines the tree up with the real game, keeping what a newly revealed queue still allows
+    pub fn rebase(&mut self, game: &Game) -> bool {
+        let same = self.game.board.get_arr() == game.board.get_arr()
+            && self.game.active_piece.get_type() == game.active_piece.get_type()
+            && self.game.hold_piece == game.hold_piece
+            && self.game.game_data.b2b == game.game_data.b2b
+            && self.game.game_data.combo == game.game_data.combo
+            && self.game.game_data.surge == game.game_data.surge
+            && self.game.garbage_queue == game.garbage_queue;
+        if !same {
+            return false;
+        }
+
+        if self.game.piece_queue.get_queue() != game.piece_queue.get_queue() {
+            let placements = mem::take(&mut self.placements);
+            let moves = mem::take(&mut self.moves);
+            let scores = mem::take(&mut self.scores);
+            for (moves, placements, score) in izip!(moves, placements, scores) {
+                if consistent(game, &placements) {
+                    self.moves.push(moves);
+                    self.placements.push(placements);
+                    self.scores.push(score);
+                }
+            }
+        }
+        self.game = game.clone();
+        !self.placements.is_empty()
+    }
+}
+
+// a tree growing on another thread until it's stopped or reaches full depth
+pub struct Background {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<SearchTree>>,
+}
+
+impl Background {
+    pub fn spawn(
+        mut tree: SearchTree,
+        search: SearchConfig,
+        weights: Weights,
+        pool: Option<Arc<ThreadPool>>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let handle = thread::spawn(move || {
+            let limits = Limits::new(Budget::Unlimited, Some(&flag));
+            tree.grow(&search, &weights, &limits, pool.as_deref());
+            tree
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn stop(mut self) -> Option<SearchTree> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.take()?.join().ok()
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn play(game: &mut Game, placement: &Piece) {
+    if game.active_piece.piece_type != placement.piece_type {
+        // need to hold
+        game.hold();
+        assert_eq!(game.active_piece.piece_type, placement.piece_type);
+    }
+    game.set_active_piece(*placement);
+    game.set_piece();
+}
+
+pub(crate) fn consistent(game: &Game, placements: &PlacementList) -> bool {
+    // only the piece types matter, pieces past the end of the queue aren't known yet
+    let mut queue: VecDeque<PieceType> = game.piece_queue.get_queue().clone();
+    let mut active = Some(game.active_piece.get_type());
+    let mut hold = game.hold_piece;
+
+    for placement in placements {
+        if active.is_none() {
+            return true;
+        }
+        if active != Some(placement.piece_type) {
+            // holding swaps, or takes the next piece when the hold is empty
+            let held = hold.take().or_else(|| queue.pop_front());
+            hold = active;
+            active = held;
+            if active.is_none() {
+                return true;
+            }
+            if active != Some(placement.piece_type) {
+                return false;
+            }
+        }
+        active = queue.pop_front();
+    }
+    true
+}
+
+#[cfg(test)]
+mod search_tree_tests {
+    use super::*;
+    use crate::players::{do_move_list, Player};
+
+    fn shallow() -> SearchConfig {
+        SearchConfig {
+            max_depth: 3,
+            beam_width: 5,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let game = Game::new(Some(2));
+        let weights = Weights::default();
+        let mut tree = SearchTree::new(&game, &weights);
+        assert!(tree.grow(&shallow(), &weights, &Limits::new(Budget::Unlimited, None), None));
+        assert_eq!(tree.get_depth(), 3);
+
+        let best = tree.best().unwrap().1.clone();
+        assert!(tree.advance(&best[0]));
+        assert_eq!(tree.get_depth(), 2);
+        assert!(tree.frontier().1.iter().all(|p| p.len() == 2));
+        assert!(tree.frontier().1.contains(&best[1..].to_vec()));
+        // every line still has real inputs for its first placement
+        let (moves, placements, _) = tree.frontier();
+        for (moves, placements) in zip(moves, placements) {
+            let mut replayed = tree.get_game().clone();
+            do_move_list(&mut replayed, moves.clone());
+            assert_eq!(replayed.ret_active_drop(), placements[0]);
+        }
+
+        let mut played = game.clone();
+        play(&mut played, &best[0]);
+        // garbage that came in since, or a charged surge, makes the old lines wrong
+        let mut attacked = played.clone();
+        attacked.receive_garbage(2, 0);
+        assert!(!tree.rebase(&attacked));
+        let mut charged = played.clone();
+        charged.game_data.surge = 4;
+        assert!(!tree.rebase(&charged));
+        assert!(tree.rebase(&played));
+        assert!(!tree.rebase(&game));
+    }
+
+    #[test]
+    fn test_transpositions() {
+        let game = Game::new(Some(2));
+        let weights = Weights::default();
+        let mut tree = SearchTree::new(&game, &weights);
+        let search = SearchConfig {
+            max_depth: 4,
+            beam_width: 5,
+            prune_depth: 1,
+            ..Default::default()
+        };
+        assert!(tree.grow(&search, &weights, &Limits::new(Budget::Unlimited, None), None));
+
+        // the last level expanded a full beam of distinct states, duplicates don't take up a slot
+        let (_, placements, scores) = tree.frontier();
+        let parents: HashSet<u64> = placements
+            .iter()
+            .map(|line| {
+                let mut parent = game.clone();
+                for p in &line[..line.len() - 1] {
+                    play(&mut parent, p);
+                }
+                parent.state_hash()
+            })
+            .collect();
+        assert_eq!(parents.len(), 5);
+        assert!(!tree.board_scores.is_empty());
+        let mut dummy = game.clone();
+        for p in &placements[0][..placements[0].len() - 1] {
+            play(&mut dummy, p);
+        }
+        let mut fresh = ScoreCache::new();
+        let (_, _, expected) = Bot::move_placement_score_cached(&mut dummy, &weights, &ScoreCache::new(), &mut fresh);
+        assert!(expected.iter().any(|(board, _)| *board == scores[0].0));
+    }
+
+    #[test]
+    fn test_reuse() {
+        let mut bot = Bot::new(Game::new(Some(2)));
+        bot.set_search_config(shallow());
+
+        // asking for a move without playing it leaves the tree where it was
+        let first = bot.get_next_move();
+        assert_eq!(bot.get_next_move(), first);
+        assert_eq!(bot.get_search_tree().unwrap().get_depth(), 3);
+        assert_eq!(bot.get_search_tree().unwrap().get_game().board.get_arr(), bot.get_game().board.get_arr());
+
+        bot.make_move();
+
+        // the tree was advanced past the move that was played, and grows back from there
+        assert_eq!(bot.get_search_tree().unwrap().get_depth(), 2);
+        assert!(bot.think(Budget::Unlimited));
+        assert_eq!(bot.get_search_tree().unwrap().get_depth(), 3);
+    }
+
+    #[test]
+    fn test_background() {
+        let mut bot = Bot::new(Game::new(Some(2)));
+        bot.set_search_config(shallow());
+        bot.think_in_background();
+        bot.stop_thinking();
+        assert!(bot.get_search_tree().is_some());
+        assert!(bot.make_move());
+    }
+
+    #[test]
+    fn test_revealed_queue() {
+        let mut game = Game::new(Some(2));
+        let weights = Weights::default();
+        let mut tree = SearchTree::new(&game, &weights);
+        tree.grow(&shallow(), &weights, &Limits::new(Budget::Unlimited, None), None);
+
+        // a different next piece rules out every line that played the old one
+        let mut queue = game.piece_queue.get_queue().clone();
+        let next = queue[0];
+        queue[0] = (next + 1) % 7;
+        game.piece_queue.set_queue(queue);
+        let active = game.active_piece.get_type();
+        tree.rebase(&game);
+        assert!(tree
+            .frontier()
+            .1
+            .iter()
+            .all(|p| consistent(&game, p) && (p[0].piece_type == active || p[0].piece_type != next)));
+    }
+}
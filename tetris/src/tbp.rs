@@ -0,0 +1,645 @@
+#![allow(dead_code)]
+
+// the tetris bot protocol, https://github.com/tetris-bot-protocol/tbp-spec
+use crate::bot::Bot;
+use crate::communications::server_bot;
+use crate::constants::board_constants::{BOARD_HEIGHT, BOARD_WIDTH};
+use crate::constants::rotation::PIECE_ROTATIONS;
+use crate::constants::types::*;
+use crate::constants::versus_constants::TSpinType;
+use crate::game::game_rules_and_data::{GameRules, SpinBonus};
+use crate::game::Game;
+use crate::piece::Piece;
+use crate::players::{do_move_list, Player};
+use crate::point_vector::Point;
+use crate::queue::BagType;
+use crate::search_tree::play;
+use crate::weight::Weights;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use tokio::net::TcpListener;
+use tokio_tungstenite::accept_async;
+use tungstenite::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TbpPiece {
+    Z,
+    L,
+    O,
+    S,
+    I,
+    J,
+    T,
+}
+
+impl TbpPiece {
+    // the same order as piece types
+    const ALL: [TbpPiece; 7] = [TbpPiece::Z, TbpPiece::L, TbpPiece::O, TbpPiece::S, TbpPiece::I, TbpPiece::J, TbpPiece::T];
+
+    pub fn from_type(piece_type: PieceType) -> Self {
+        TbpPiece::ALL[piece_type]
+    }
+
+    pub fn get_type(self) -> PieceType {
+        self as PieceType
+    }
+
+    // minos around the center pointing north, as (x, y) with y going up
+    fn cells(self) -> [(i8, i8); 4] {
+        match self {
+            TbpPiece::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+            TbpPiece::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            TbpPiece::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+            TbpPiece::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+            TbpPiece::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+            TbpPiece::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+            TbpPiece::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Orientation {
+    const ALL: [Orientation; 4] = [Orientation::North, Orientation::East, Orientation::South, Orientation::West];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(rename = "type")]
+    pub kind: TbpPiece,
+    pub orientation: Orientation,
+    pub x: i8,
+    pub y: i8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TbpMove {
+    pub location: Location,
+    pub spin: Spin,
+}
+
+impl TbpMove {
+    pub fn from_piece(piece: &Piece, spin: TSpinType) -> Self {
+        let kind = TbpPiece::from_type(piece.get_type());
+        let rotation = piece.get_rotation_state();
+        let (row, col) = center_offset(kind, rotation);
+        let spin = match spin {
+            TSpinType::None => Spin::None,
+            TSpinType::Mini => Spin::Mini,
+            TSpinType::Full => Spin::Full,
+        };
+
+        Self {
+            location: Location {
+                kind,
+                orientation: Orientation::ALL[rotation],
+                x: piece.center.1 - col,
+                y: piece.center.0 - row,
+            },
+            spin,
+        }
+    }
+
+    pub fn to_piece(self) -> Piece {
+        let kind = self.location.kind;
+        let rotation = self.location.orientation as RotationState;
+        let (row, col) = center_offset(kind, rotation);
+        Piece {
+            rotation_state: rotation,
+            center: Point(self.location.y + row, self.location.x + col),
+            // a spin has to have come from a rotation
+            last_kick: if self.spin == Spin::None { 999 } else { 0 },
+            ..Piece::new(kind.get_type())
+        }
+    }
+}
+
+// how far our center sits from the tbp one, as (row, col)
+fn center_offset(kind: TbpPiece, rotation: RotationState) -> (i8, i8) {
+    let ours = PIECE_ROTATIONS[kind.get_type()][rotation]
+        .iter()
+        .map(|p| (p.0, p.1))
+        .min()
+        .unwrap();
+    let theirs = kind
+        .cells()
+        .iter()
+        .map(|&(x, y)| (0..rotation).fold((x, y), |(x, y), _| (y, -x)))
+        .map(|(x, y)| (y, x))
+        .min()
+        .unwrap();
+    (theirs.0 - ours.0, theirs.1 - ours.1)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FrontendMessage {
+    Rules {
+        #[serde(default)]
+        randomizer: Option<String>,
+        #[serde(default)]
+        spins: Option<String>,
+    },
+    Start {
+        hold: Option<TbpPiece>,
+        queue: Vec<TbpPiece>,
+        #[serde(default)]
+        combo: u32,
+        #[serde(default)]
+        back_to_back: bool,
+        // rows from the bottom, any non-null cell is filled
+        board: Vec<Vec<Option<String>>>,
+    },
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: TbpMove,
+    },
+    NewPiece {
+        piece: TbpPiece,
+    },
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+        author: String,
+        features: Vec<String>,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<TbpMove>,
+    },
+}
+
+impl BotMessage {
+    pub fn info() -> Self {
+        BotMessage::Info {
+            name: "IVFISH".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            author: "IVFISH".to_string(),
+            features: Vec::new(),
+        }
+    }
+}
+
+fn bag_type(randomizer: &str) -> Option<BagType> {
+    Some(match randomizer {
+        "seven_bag" => BagType::SevenBag,
+        "fourteen_bag" | "general_bag" => BagType::FourteenBag,
+        "classic" => BagType::Classic,
+        "pairs" => BagType::Pairs,
+        "uniform" => BagType::Mayhem,
+        _ => return None,
+    })
+}
+
+fn spin_bonus(spins: &str) -> Option<SpinBonus> {
+    Some(match spins {
+        "t" | "t_only" => SpinBonus::TSpin,
+        "all" | "all_mini" => SpinBonus::All,
+        "stupid" => SpinBonus::Stupid,
+        "none" => SpinBonus::None,
+        _ => return None,
+    })
+}
+
+// one frontend's game, independent of how the messages get here
+pub struct TbpSession {
+    weights: Weights,
+    rules: GameRules,
+    bot: Option<Bot>,
+    quit: bool,
+}
+
+impl TbpSession {
+    pub fn new(weights: Weights) -> Self {
+        Self {
+            weights,
+            rules: GameRules::default(),
+            bot: None,
+            quit: false,
+        }
+    }
+
+    pub fn get_bot(&self) -> Option<&Bot> {
+        self.bot.as_ref()
+    }
+
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn handle(&mut self, message: FrontendMessage) -> Option<BotMessage> {
+        match message {
+            FrontendMessage::Rules { randomizer, spins } => {
+                let mut rules = GameRules::default();
+                if let Some(randomizer) = randomizer {
+                    match bag_type(&randomizer) {
+                        Some(bag) => rules.bag_type = bag,
+                        None => return Some(unsupported()),
+                    }
+                }
+                if let Some(spins) = spins {
+                    match spin_bonus(&spins) {
+                        Some(bonus) => rules.spin_bonus = bonus,
+                        None => return Some(unsupported()),
+                    }
+                }
+                self.rules = rules;
+                Some(BotMessage::Ready)
+            }
+            FrontendMessage::Start {
+                hold,
+                queue,
+                combo,
+                back_to_back,
+                board,
+            } => {
+                self.bot = self.start(hold, queue, combo, back_to_back, board);
+                None
+            }
+            FrontendMessage::Stop => {
+                if let Some(bot) = self.bot.as_mut() {
+                    bot.stop_thinking();
+                }
+                self.bot = None;
+                None
+            }
+            FrontendMessage::Suggest => {
+                let bot = self.bot.as_mut()?;
+                Some(BotMessage::Suggestion {
+                    moves: suggest(bot).into_iter().collect(),
+                })
+            }
+            FrontendMessage::Play { mv } => {
+                if let Some(bot) = self.bot.as_mut() {
+                    if !play_move(bot, &mv) {
+                        eprintln!("ignoring a move that can't be played: {:?}", mv);
+                    }
+                    bot.think_in_background();
+                }
+                None
+            }
+            FrontendMessage::NewPiece { piece } => {
+                if let Some(bot) = self.bot.as_mut() {
+                    let mut queue = bot.get_game().piece_queue.get_queue().clone();
+                    queue.push_back(piece.get_type());
+                    bot.get_game_mut().piece_queue.set_queue(queue);
+                }
+                None
+            }
+            FrontendMessage::Quit => {
+                if let Some(bot) = self.bot.as_mut() {
+                    bot.stop_thinking();
+                }
+                self.quit = true;
+                None
+            }
+        }
+    }
+
+    fn start(
+        &self,
+        hold: Option<TbpPiece>,
+        queue: Vec<TbpPiece>,
+        combo: u32,
+        back_to_back: bool,
+        board: Vec<Vec<Option<String>>>,
+    ) -> Option<Bot> {
+        // the first piece of the queue is the one being played
+        let mut queue: VecDeque<PieceType> = queue.into_iter().map(TbpPiece::get_type).collect();
+        let active = queue.pop_front()?;
+
+        let mut game = Game::from_rules(None, self.rules.clone());
+        for (row, cells) in board.iter().enumerate().take(BOARD_HEIGHT) {
+            game.board
+                .set_row(row, cells.iter().take(BOARD_WIDTH).map(Option::is_some).collect());
+        }
+        game.set_active_piece(Piece::new(active));
+        game.hold_piece = hold.map(TbpPiece::get_type);
+        game.piece_queue.set_queue(queue);
+        // combos past what the game counts are all worth the same
+        game.game_data.combo = combo.min(i8::MAX as u32) as i8;
+        game.game_data.b2b = i8::from(back_to_back);
+
+        let mut bot = server_bot(game, &self.weights);
+        bot.think_in_background();
+        Some(bot)
+    }
+}
+
+fn unsupported() -> BotMessage {
+    BotMessage::Error {
+        reason: "unsupported_rules".to_string(),
+    }
+}
+
+fn suggest(bot: &mut Bot) -> Option<TbpMove> {
+    // only a question, the bot moves on when the frontend says what was played.
+    // play the inputs out on a copy to see where the piece ends up
+    let mut commands = bot.get_next_move();
+    commands.pop()?;
+    let mut game = bot.get_game().clone();
+    do_move_list(&mut game, commands);
+    let placement = game.ret_active_drop();
+    Some(TbpMove::from_piece(&placement, game.get_spin_type(&placement)))
+}
+
+// false if the move isn't one the game can make, leaving the game as it was
+fn play_move(bot: &mut Bot, mv: &TbpMove) -> bool {
+    let game = bot.get_game_mut();
+    let placement = mv.to_piece();
+    // the active piece, or whatever holding would bring in
+    let swapped = game.hold_piece.or_else(|| game.piece_queue.get_queue().front().copied());
+    if placement.get_type() != game.active_piece.get_type() && Some(placement.get_type()) != swapped {
+        return false;
+    }
+    if !game.board.piece_valid_placement(&placement) {
+        return false;
+    }
+
+    // only the pieces the frontend told us about are kept, not the ones the queue makes up
+    let known = game.piece_queue.get_queue().len();
+    let consumed = if game.active_piece.get_type() != placement.get_type() && game.hold_piece.is_none() {
+        2
+    } else {
+        1
+    };
+    play(game, &placement);
+    let mut queue = game.piece_queue.get_queue().clone();
+    queue.truncate(known.saturating_sub(consumed));
+    game.piece_queue.set_queue(queue);
+    bot.commit(&placement);
+    true
+}
+
+fn parse(line: &str) -> Option<FrontendMessage> {
+    // unknown messages are ignored, as the protocol asks
+    serde_json::from_str(line)
+        .map_err(|e| eprintln!("ignoring tbp message {}: {}", line, e))
+        .ok()
+}
+
+// one frontend over stdin and stdout, a json message per line
+pub fn run_stdio(weights: Weights) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    writeln!(stdout, "{}", serde_json::to_string(&BotMessage::info())?)?;
+    stdout.flush()?;
+
+    let mut session = TbpSession::new(weights);
+    for line in io::stdin().lock().lines() {
+        let Some(message) = parse(&line?) else {
+            continue;
+        };
+        if let Some(reply) = session.handle(message) {
+            writeln!(stdout, "{}", serde_json::to_string(&reply)?)?;
+            stdout.flush()?;
+        }
+        if session.quit() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// a frontend per websocket connection, a json message per text frame
+#[tokio::main]
+pub async fn run_websocket(addr: &str, weights: Weights) -> io::Result<()> {
+    serve_websocket(TcpListener::bind(addr).await?, weights).await
+}
+
+async fn serve_websocket(listener: TcpListener, weights: Weights) -> io::Result<()> {
+    while let Ok((stream, _)) = listener.accept().await {
+        let weights = weights.clone();
+        tokio::spawn(async move {
+            let Ok(ws_stream) = accept_async(stream).await else {
+                return;
+            };
+            let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+            let info = serde_json::to_string(&BotMessage::info()).unwrap();
+            if ws_sender.send(Message::Text(info)).await.is_err() {
+                return;
+            }
+
+            let mut session = TbpSession::new(weights);
+            while let Some(Ok(message)) = ws_receiver.next().await {
+                let Ok(text) = message.to_text() else {
+                    continue;
+                };
+                let Some(message) = parse(text) else {
+                    continue;
+                };
+                // the bot thinks synchronously, so keep it off the async workers
+                let (reply, returned) = tokio::task::block_in_place(|| {
+                    let reply = session.handle(message);
+                    (reply, session)
+                });
+                session = returned;
+                if let Some(reply) = reply {
+                    let text = serde_json::to_string(&reply).unwrap();
+                    if ws_sender.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                if session.quit() {
+                    break;
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tbp_tests {
+    use super::*;
+
+    #[test]
+    fn test_locations() {
+        // the spec's minos for every piece and orientation land on ours
+        for piece_type in 0..7 {
+            for rotation in 0..4 {
+                let piece = Piece {
+                    rotation_state: rotation,
+                    center: Point(5, 4),
+                    ..Piece::new(piece_type)
+                };
+                let mv = TbpMove::from_piece(&piece, TSpinType::None);
+                let kind = mv.location.kind;
+                let mut theirs: Vec<Point> = kind
+                    .cells()
+                    .iter()
+                    .map(|&(x, y)| (0..rotation).fold((x, y), |(x, y), _| (y, -x)))
+                    .map(|(x, y)| Point(mv.location.y + y, mv.location.x + x))
+                    .collect();
+                let mut ours = piece.abs_locations().unwrap();
+                theirs.sort_by_key(|p| (p.0, p.1));
+                ours.sort_by_key(|p| (p.0, p.1));
+                assert_eq!(theirs, ours);
+                assert_eq!(mv.to_piece().center, piece.center);
+            }
+        }
+
+        // a t pointing up has its center on the middle mino
+        let t = TbpMove::from_piece(&Piece::new(6), TSpinType::None);
+        assert_eq!((t.location.x, t.location.y), (Piece::new(6).center.1, Piece::new(6).center.0));
+    }
+
+    #[test]
+    fn test_messages() {
+        let rules: FrontendMessage = serde_json::from_str(r#"{"type":"rules","randomizer":"seven_bag"}"#).unwrap();
+        let mut session = TbpSession::new(Weights::default());
+        assert_eq!(session.handle(rules), Some(BotMessage::Ready));
+        let bad: FrontendMessage = serde_json::from_str(r#"{"type":"rules","randomizer":"nope"}"#).unwrap();
+        assert_eq!(session.handle(bad), Some(unsupported()));
+
+        let mv: FrontendMessage = serde_json::from_str(
+            r#"{"type":"play","move":{"location":{"type":"T","orientation":"south","x":4,"y":1},"spin":"full"}}"#,
+        )
+        .unwrap();
+        let FrontendMessage::Play { mv } = mv else { panic!() };
+        assert_eq!(mv.location.orientation, Orientation::South);
+        assert_eq!(mv.spin, Spin::Full);
+
+        let suggestion = serde_json::to_value(BotMessage::Suggestion { moves: vec![mv] }).unwrap();
+        assert_eq!(suggestion["type"], "suggestion");
+        assert_eq!(suggestion["moves"][0]["location"]["type"], "T");
+        assert!(parse(r#"{"type":"something_new"}"#).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_websocket(listener, Weights::default()));
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+        let info = serde_json::to_string(&BotMessage::info()).unwrap();
+        assert_eq!(socket.next().await.unwrap().unwrap(), Message::Text(info));
+
+        let send = |message: &str| Message::Text(message.to_string());
+        socket.send(send(r#"{"type":"rules"}"#)).await.unwrap();
+        assert_eq!(socket.next().await.unwrap().unwrap(), send(r#"{"type":"ready"}"#));
+
+        let board = serde_json::to_string(&vec![vec![None::<String>; 10]; 40]).unwrap();
+        let start = format!(
+            r#"{{"type":"start","hold":null,"queue":["T","I","O","S","Z","L"],"combo":0,"back_to_back":false,"board":{}}}"#,
+            board
+        );
+        socket.send(send(&start)).await.unwrap();
+        socket.send(send(r#"{"type":"suggest"}"#)).await.unwrap();
+        let text = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        let suggestion: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(suggestion["type"], "suggestion");
+        assert!(["T", "I"].contains(&suggestion["moves"][0]["location"]["type"].as_str().unwrap()));
+        socket.send(send(r#"{"type":"quit"}"#)).await.unwrap();
+    }
+
+    #[test]
+    fn test_session() {
+        let mut session = TbpSession::new(Weights::default());
+        session.handle(FrontendMessage::Rules {
+            randomizer: None,
+            spins: None,
+        });
+        let mut board = vec![vec![None; 10]; 40];
+        board[0] = vec![Some("G".to_string()); 9];
+        board[0].push(None);
+        session.handle(FrontendMessage::Start {
+            hold: None,
+            queue: vec![TbpPiece::I, TbpPiece::O, TbpPiece::T, TbpPiece::S, TbpPiece::Z, TbpPiece::L],
+            combo: 0,
+            back_to_back: false,
+            board,
+        });
+        assert_eq!(session.get_bot().unwrap().get_game().board.get_mino_count(), 9);
+
+        let Some(BotMessage::Suggestion { moves }) = session.handle(FrontendMessage::Suggest) else {
+            panic!("no suggestion");
+        };
+        let placement = moves[0].to_piece();
+        let game = session.get_bot().unwrap().get_game();
+        assert!(game.board.piece_valid_placement(&placement));
+        // asking again changes nothing
+        let Some(BotMessage::Suggestion { moves: again }) = session.handle(FrontendMessage::Suggest) else {
+            panic!("no suggestion");
+        };
+        assert_eq!(again, moves);
+        assert_eq!(session.get_bot().unwrap().get_game().board.get_mino_count(), 9);
+
+        // the frontend plays something else, and the queue is only what it said
+        let mv = TbpMove::from_piece(
+            &Piece {
+                center: Point(1, 2),
+                ..Piece::new(2)
+            },
+            TSpinType::None,
+        );
+        session.handle(FrontendMessage::Play { mv });
+
+        // a piece that isn't active or held, and one floating in the air, are both ignored
+        let before = session.get_bot().unwrap().get_game().board.get_arr();
+        for piece in [
+            Piece {
+                center: Point(1, 6),
+                ..Piece::new(0)
+            },
+            Piece {
+                center: Point(10, 4),
+                ..Piece::new(6)
+            },
+        ] {
+            session.handle(FrontendMessage::Play {
+                mv: TbpMove::from_piece(&piece, TSpinType::None),
+            });
+        }
+        assert_eq!(session.get_bot().unwrap().get_game().board.get_arr(), before);
+        session.handle(FrontendMessage::NewPiece { piece: TbpPiece::J });
+        let game = session.get_bot().unwrap().get_game();
+        assert_eq!(game.hold_piece, Some(4));
+        assert_eq!(game.active_piece.get_type(), 6);
+        assert_eq!(game.piece_queue.get_vec(), vec![3, 0, 1, 5]);
+        session.handle(FrontendMessage::Quit);
+        assert!(session.quit());
+
+        // a combo too long for the game is held at the most it counts
+        let mut session = TbpSession::new(Weights::default());
+        session.handle(FrontendMessage::Start {
+            hold: None,
+            queue: vec![TbpPiece::T, TbpPiece::I],
+            combo: u32::MAX,
+            back_to_back: true,
+            board: Vec::new(),
+        });
+        let data = &session.get_bot().unwrap().get_game().game_data;
+        assert_eq!((data.combo, data.b2b), (i8::MAX, 1));
+        session.handle(FrontendMessage::Quit);
+    }
+}
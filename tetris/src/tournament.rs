@@ -0,0 +1,432 @@
+#![allow(dead_code)]
+
+use crate::bot::Bot;
+use crate::game::Game;
+use crate::versus_match::{Match, Winner};
+use crate::weight::Weights;
+use serde::Serialize;
+use std::f32::consts::PI;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+const DEFAULT_MAX_PIECES: usize = 500;
+
+const ELO_START: f32 = 1500.0;
+const ELO_K: f32 = 16.0;
+
+const GLICKO_START: f32 = 1500.0;
+const GLICKO_START_RD: f32 = 350.0;
+const GLICKO_MIN_RD: f32 = 30.0;
+
+// z score for a 95% confidence interval
+const CONFIDENCE_Z: f32 = 1.96;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pairing {
+    RoundRobin,
+    Swiss(usize),
+}
+
+pub struct Tournament {
+    pub entrants: Vec<(String, Weights)>,
+    pub seeds: Vec<usize>,
+    pub pairing: Pairing,
+    pub max_pieces: usize,
+    pub threads: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Fixture {
+    round: usize,
+    one: usize,
+    two: usize,
+    seed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameRecord {
+    pub round: usize,
+    pub one: String,
+    pub two: String,
+    pub seed: usize,
+    pub winner: Winner,
+    pub pieces: [usize; 2],
+    // attack per piece, the rates after it are measured against think time
+    pub app: [f32; 2],
+    pub apm: [f32; 2],
+    pub pps: [f32; 2],
+    // real time per piece
+    pub think_ms: [f32; 2],
+
+    #[serde(skip)]
+    indices: (usize, usize),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Standing {
+    pub name: String,
+    pub games: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+    pub win_rate: f32,
+    pub win_rate_low: f32,
+    pub win_rate_high: f32,
+    pub elo: f32,
+    pub glicko: f32,
+    pub glicko_rd: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TournamentReport {
+    pub standings: Vec<Standing>,
+    pub games: Vec<GameRecord>,
+}
+
+impl Display for TournamentReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>5} {:>5} {:>5} {:>8} {:>17} {:>8} {:>14}",
+            "name", "games", "wins", "loss", "draw", "win%", "95% ci", "elo", "glicko"
+        )?;
+        for s in &self.standings {
+            writeln!(
+                f,
+                "{:<16} {:>6} {:>5} {:>5} {:>5} {:>7.1}% {:>7.1}% - {:>5.1}% {:>8.0} {:>7.0} ± {:>4.0}",
+                s.name,
+                s.games,
+                s.wins,
+                s.losses,
+                s.draws,
+                100.0 * s.win_rate,
+                100.0 * s.win_rate_low,
+                100.0 * s.win_rate_high,
+                s.elo,
+                s.glicko,
+                2.0 * s.glicko_rd,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl TournamentReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl Tournament {
+    pub fn new(entrants: Vec<(String, Weights)>, seeds: Vec<usize>, pairing: Pairing) -> Self {
+        Self {
+            entrants,
+            seeds,
+            pairing,
+            max_pieces: DEFAULT_MAX_PIECES,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    pub fn run(&self) -> TournamentReport {
+        let mut records = Vec::new();
+
+        match self.pairing {
+            Pairing::RoundRobin => {
+                let fixtures = round_robin(self.entrants.len(), &self.seeds);
+                records = self.play_fixtures(&fixtures);
+            }
+            Pairing::Swiss(rounds) => {
+                for round in 0..rounds {
+                    let fixtures = swiss_round(round, self.entrants.len(), &self.seeds, &records);
+                    if fixtures.is_empty() {
+                        break;
+                    }
+                    records.extend(self.play_fixtures(&fixtures));
+                }
+            }
+        }
+
+        TournamentReport {
+            standings: self.standings(&records),
+            games: records,
+        }
+    }
+
+    fn play_fixtures(&self, fixtures: &[Fixture]) -> Vec<GameRecord> {
+        // games are handed out to worker threads one at a time and stored by index,
+        // so the result order does not depend on the thread count
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<GameRecord>>> = Mutex::new(vec![None; fixtures.len()]);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= fixtures.len() {
+                        break;
+                    }
+                    let record = self.play_fixture(&fixtures[index]);
+                    results.lock().unwrap()[index] = Some(record);
+                });
+            }
+        });
+
+        results.into_inner().unwrap().into_iter().flatten().collect()
+    }
+
+    fn play_fixture(&self, fixture: &Fixture) -> GameRecord {
+        let (one_name, one_weights) = &self.entrants[fixture.one];
+        let (two_name, two_weights) = &self.entrants[fixture.two];
+        let one = Bot::from_weights(Game::new(Some(fixture.seed)), one_weights.clone());
+        let two = Bot::from_weights(Game::new(Some(fixture.seed)), two_weights.clone());
+
+        let result = Match::new(fixture.seed, one, two, self.max_pieces).play();
+
+        GameRecord {
+            round: fixture.round,
+            one: one_name.clone(),
+            two: two_name.clone(),
+            seed: fixture.seed,
+            winner: result.winner,
+            pieces: [result.stats[0].pieces, result.stats[1].pieces],
+            app: [result.stats[0].app(), result.stats[1].app()],
+            apm: [result.stats[0].apm(), result.stats[1].apm()],
+            pps: [result.stats[0].pps(), result.stats[1].pps()],
+            think_ms: [
+                result.stats[0].think_per_piece().as_secs_f32() * 1000.0,
+                result.stats[1].think_per_piece().as_secs_f32() * 1000.0,
+            ],
+            indices: (fixture.one, fixture.two),
+        }
+    }
+
+    fn standings(&self, records: &[GameRecord]) -> Vec<Standing> {
+        let n = self.entrants.len();
+        let elo = elo_ratings(n, records);
+        let glicko = glicko_ratings(n, records);
+
+        let mut out: Vec<Standing> = (0..n)
+            .map(|i| {
+                let (mut wins, mut losses, mut draws) = (0, 0, 0);
+                for (score, _) in player_games(i, records) {
+                    match score {
+                        s if s > 0.5 => wins += 1,
+                        s if s < 0.5 => losses += 1,
+                        _ => draws += 1,
+                    }
+                }
+                let games = wins + losses + draws;
+                let (win_rate, win_rate_low, win_rate_high) =
+                    wilson_interval(wins as f32 + 0.5 * draws as f32, games);
+
+                Standing {
+                    name: self.entrants[i].0.clone(),
+                    games,
+                    wins,
+                    losses,
+                    draws,
+                    win_rate,
+                    win_rate_low,
+                    win_rate_high,
+                    elo: elo[i],
+                    glicko: glicko[i].0,
+                    glicko_rd: glicko[i].1,
+                }
+            })
+            .collect();
+
+        out.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap());
+        out
+    }
+}
+
+// pairings
+fn round_robin(n: usize, seeds: &[usize]) -> Vec<Fixture> {
+    // each pair plays every seed from both sides, since player one moves first
+    let mut out = Vec::new();
+    for one in 0..n {
+        for two in (one + 1)..n {
+            out.extend(both_sides(0, one, two, seeds));
+        }
+    }
+    out
+}
+
+fn swiss_round(round: usize, n: usize, seeds: &[usize], records: &[GameRecord]) -> Vec<Fixture> {
+    // pairs neighbours in the standings, avoiding rematches where possible
+    let scores: Vec<f32> = (0..n)
+        .map(|i| player_games(i, records).map(|(score, _)| score).sum())
+        .collect();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap().then(a.cmp(&b)));
+
+    let played = |a: usize, b: usize| {
+        records
+            .iter()
+            .any(|r| r.indices == (a, b) || r.indices == (b, a))
+    };
+
+    let mut out = Vec::new();
+    while order.len() > 1 {
+        let one = order.remove(0);
+        let index = order.iter().position(|&two| !played(one, two)).unwrap_or(0);
+        let two = order.remove(index);
+        out.extend(both_sides(round, one, two, seeds));
+    }
+    out
+}
+
+fn both_sides(round: usize, one: usize, two: usize, seeds: &[usize]) -> Vec<Fixture> {
+    seeds
+        .iter()
+        .flat_map(|&seed| {
+            [
+                Fixture { round, one, two, seed },
+                Fixture { round, one: two, two: one, seed },
+            ]
+        })
+        .collect()
+}
+
+// (score, opponent) for every game a player took part in
+fn player_games(player: usize, records: &[GameRecord]) -> impl Iterator<Item = (f32, usize)> + '_ {
+    records.iter().filter_map(move |record| {
+        let (one, two) = record.indices;
+        let score = match record.winner {
+            Winner::PlayerOne => 1.0,
+            Winner::PlayerTwo => 0.0,
+            Winner::Draw => 0.5,
+        };
+        if one == player {
+            Some((score, two))
+        } else if two == player {
+            Some((1.0 - score, one))
+        } else {
+            None
+        }
+    })
+}
+
+// ratings
+fn expected_score(rating: f32, opponent: f32) -> f32 {
+    1.0 / (1.0 + 10_f32.powf((opponent - rating) / 400.0))
+}
+
+fn elo_ratings(n: usize, records: &[GameRecord]) -> Vec<f32> {
+    let mut ratings = vec![ELO_START; n];
+    for record in records {
+        let (one, two) = record.indices;
+        let score = match record.winner {
+            Winner::PlayerOne => 1.0,
+            Winner::PlayerTwo => 0.0,
+            Winner::Draw => 0.5,
+        };
+        let change = ELO_K * (score - expected_score(ratings[one], ratings[two]));
+        ratings[one] += change;
+        ratings[two] -= change;
+    }
+    ratings
+}
+
+fn glicko_ratings(n: usize, records: &[GameRecord]) -> Vec<(f32, f32)> {
+    // glicko-1, with every round of the tournament as one rating period
+    let q = 10_f32.ln() / 400.0;
+    let g = |rd: f32| 1.0 / (1.0 + 3.0 * q * q * rd * rd / (PI * PI)).sqrt();
+
+    let mut ratings = vec![(GLICKO_START, GLICKO_START_RD); n];
+    let rounds = records.iter().map(|r| r.round + 1).max().unwrap_or(0);
+
+    for round in 0..rounds {
+        let period: Vec<GameRecord> = records.iter().filter(|r| r.round == round).cloned().collect();
+        let mut next = ratings.clone();
+
+        for (player, rating) in next.iter_mut().enumerate() {
+            let (r, rd) = ratings[player];
+            let mut d_inverse = 0.0;
+            let mut total = 0.0;
+            for (score, opponent) in player_games(player, &period) {
+                let (r_j, rd_j) = ratings[opponent];
+                let g_j = g(rd_j);
+                let e = 1.0 / (1.0 + 10_f32.powf(-g_j * (r - r_j) / 400.0));
+                d_inverse += q * q * g_j * g_j * e * (1.0 - e);
+                total += g_j * (score - e);
+            }
+            if d_inverse == 0.0 {
+                continue;
+            }
+            let denominator = 1.0 / (rd * rd) + d_inverse;
+            *rating = (r + q / denominator * total, (1.0 / denominator).sqrt().max(GLICKO_MIN_RD));
+        }
+        ratings = next;
+    }
+    ratings
+}
+
+fn wilson_interval(score: f32, games: usize) -> (f32, f32, f32) {
+    if games == 0 {
+        return (0.0, 0.0, 1.0);
+    }
+    let n = games as f32;
+    let p = score / n;
+    let z2 = CONFIDENCE_Z * CONFIDENCE_Z;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let margin = CONFIDENCE_Z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    (p, (center - margin).max(0.0), (center + margin).min(1.0))
+}
+
+#[cfg(test)]
+mod tournament_tests {
+    use super::*;
+
+    fn record(round: usize, one: usize, two: usize, winner: Winner) -> GameRecord {
+        GameRecord {
+            round,
+            one: one.to_string(),
+            two: two.to_string(),
+            seed: 0,
+            winner,
+            pieces: [0, 0],
+            app: [0.0, 0.0],
+            apm: [0.0, 0.0],
+            pps: [0.0, 0.0],
+            think_ms: [0.0, 0.0],
+            indices: (one, two),
+        }
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let fixtures = round_robin(3, &[1, 2]);
+        // 3 pairs, 2 seeds, both sides
+        assert_eq!(fixtures.len(), 12);
+        assert!(fixtures.contains(&Fixture { round: 0, one: 2, two: 0, seed: 2 }));
+    }
+
+    #[test]
+    fn test_swiss() {
+        let records = vec![record(0, 0, 1, Winner::PlayerOne), record(0, 2, 3, Winner::PlayerOne)];
+        let fixtures = swiss_round(1, 4, &[5], &records);
+        // the two winners meet, then the two losers
+        assert_eq!(fixtures[0], Fixture { round: 1, one: 0, two: 2, seed: 5 });
+        assert_eq!(fixtures[2], Fixture { round: 1, one: 1, two: 3, seed: 5 });
+    }
+
+    #[test]
+    fn test_ratings() {
+        let records: Vec<GameRecord> = (0..10).map(|_| record(0, 0, 1, Winner::PlayerOne)).collect();
+        let elo = elo_ratings(2, &records);
+        assert!(elo[0] > ELO_START && elo[1] < ELO_START);
+        assert_eq!(elo[0] + elo[1], 2.0 * ELO_START);
+
+        let glicko = glicko_ratings(2, &records);
+        assert!(glicko[0].0 > GLICKO_START && glicko[1].0 < GLICKO_START);
+        assert!(glicko[0].1 < GLICKO_START_RD);
+
+        let (p, low, high) = wilson_interval(5.0, 10);
+        assert_eq!(p, 0.5);
+        assert!((low - 0.237).abs() < 0.01 && (high - 0.763).abs() < 0.01);
+    }
+}
@@ -1,90 +1,258 @@
+use crate::constants::types::PieceType;
 use crate::constants::versus_constants::*;
-use crate::game::game_rules_and_data::GameData;
+use crate::game::game_rules_and_data::{GameData, GameRules};
+use std::str::FromStr;
 
-// d, t, q, ts, td, tt
-const ATTACK_TYPE_CONVERSION: [usize; 7] = [1, 2, 4, 2, 4, 6, 0];
+// how combos add to an attack
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ComboTable {
+    None,
+    Classic,
+    Modern,
+    // tetr.io's own, scaling the attack instead of adding to it
+    #[default]
+    Multiplier,
+}
 
-fn attack_type_to_index(attack: AttackType) -> usize {
-    match attack {
-        AttackType::D => 0,
-        AttackType::T => 1,
-        AttackType::Q => 2,
-        AttackType::TS => 3,
-        AttackType::TD => 4,
-        AttackType::TT => 5,
-        _ => 6,
+impl FromStr for ComboTable {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "none" => ComboTable::None,
+            "classic guideline" => ComboTable::Classic,
+            "modern guideline" => ComboTable::Modern,
+            "multiplier" => ComboTable::Multiplier,
+            other => {
+                eprintln!("unknown combo table '{}'", other);
+                ComboTable::Multiplier
+            }
+        })
     }
 }
 
-const BACK_TO_BACK_CONVERSION: [f32; 8] = [1.0, 1.25, 1.5, 1.75, 2.0, 2.25, 2.5, 2.75];
-const WEIRD_DAMAGE_TABLE: [[usize; 20]; 3] = [
-    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3],
-    [1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5],
-    [0; 20],
-];
+const CLASSIC_COMBO_TABLE: [f32; 11] = [0.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 4.0, 5.0];
+const MODERN_COMBO_TABLE: [f32; 13] = [0.0, 1.0, 1.0, 2.0, 2.0, 2.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 4.0];
+
+const COMBO_BONUS: f32 = 0.25;
+const COMBO_MINIFIER: f32 = 1.0;
+const COMBO_MINIFIER_LOG: f32 = 1.25;
+const BACK_TO_BACK_BONUS: f32 = 1.0;
+const BACK_TO_BACK_BONUS_LOG: f32 = 0.8;
+// without chaining, b2b starts charging a surge from b2b x4, one line per b2b
+const SURGE_START: i8 = 4;
+
+// the room settings that decide how many lines an attack sends
+#[derive(Debug, Clone, PartialEq)]
+pub struct DamageModel {
+    pub combo_table: ComboTable,
+    pub garbage_multiplier: f32,
+    // added to the multiplier every second once the margin time is over
+    pub garbage_increase: f32,
+    // in seconds
+    pub margin_time: f32,
+    pub all_clear_bonus: usize,
+}
+
+impl Default for DamageModel {
+    fn default() -> Self {
+        Self {
+            combo_table: ComboTable::default(),
+            garbage_multiplier: 1.0,
+            garbage_increase: 0.0,
+            margin_time: 0.0,
+            all_clear_bonus: 10,
+        }
+    }
+}
+
+impl DamageModel {
+    pub fn multiplier(&self, seconds: f32) -> f32 {
+        self.garbage_multiplier + self.garbage_increase * (seconds - self.margin_time).max(0.0)
+    }
+
+    // combo and b2b are the counts before this clear, so the first clear of a combo is combo 0
+    pub fn damage(&self, attack: AttackType, combo: i8, b2b: i8, chaining: bool, all_clear: bool, seconds: f32) -> usize {
+        if attack == AttackType::None {
+            return 0;
+        }
+
+        let mut garbage = base_damage(attack);
+        if b2b > 0 && BACK_TO_BACK_TYPES.contains(&attack) {
+            // without chaining the bonus stays flat, the rest charges a surge
+            garbage += if chaining { b2b_bonus(b2b) } else { BACK_TO_BACK_BONUS };
+        }
+
+        let combo = combo.max(0) as usize;
+        garbage = match self.combo_table {
+            ComboTable::None => garbage,
+            ComboTable::Classic => garbage + CLASSIC_COMBO_TABLE[combo.min(CLASSIC_COMBO_TABLE.len() - 1)],
+            ComboTable::Modern => garbage + MODERN_COMBO_TABLE[combo.min(MODERN_COMBO_TABLE.len() - 1)],
+            ComboTable::Multiplier => {
+                let mut out = garbage * (1.0 + COMBO_BONUS * combo as f32);
+                if combo > 1 {
+                    // long combos of singles still send something
+                    out = out.max((COMBO_MINIFIER * combo as f32 * COMBO_MINIFIER_LOG).ln_1p());
+                }
+                out
+            }
+        };
+
+        let mut out = (garbage * self.multiplier(seconds)).floor() as usize;
+        if all_clear {
+            out += self.all_clear_bonus;
+        }
+        out
+    }
+}
 
-fn attack_type_to_index_special(attack: AttackType) -> usize {
+impl DamageModel {
+    // what breaking a b2b chain of this length would release, b2b counting the difficult clears in a row
+    pub fn surge(&self, b2b: i8, seconds: f32) -> usize {
+        let level = b2b - 1;
+        if level < SURGE_START {
+            return 0;
+        }
+        (level as f32 * self.multiplier(seconds)).floor() as usize
+    }
+}
+
+fn base_damage(attack: AttackType) -> f32 {
     match attack {
-        AttackType::S => 0,
-        AttackType::TSM => 0,
-        AttackType::TDM => 1,
-        _ => 2,
+        AttackType::D | AttackType::TDM | AttackType::SDM => 1.0,
+        AttackType::T | AttackType::TS | AttackType::SS => 2.0,
+        AttackType::Q | AttackType::TD | AttackType::SD => 4.0,
+        AttackType::TT | AttackType::ST => 6.0,
+        _ => 0.0,
     }
 }
 
-pub const BACK_TO_BACK_TYPES: [AttackType; 6] = [
+fn b2b_bonus(b2b: i8) -> f32 {
+    let log = (b2b as f32 * BACK_TO_BACK_BONUS_LOG).ln_1p();
+    let extra = if b2b == 1 { 0.0 } else { (1.0 + log % 1.0) / 3.0 };
+    BACK_TO_BACK_BONUS * ((1.0 + log).floor() + extra)
+}
+
+pub const BACK_TO_BACK_TYPES: [AttackType; 11] = [
     AttackType::TS,
     AttackType::TD,
     AttackType::TT,
     AttackType::TSM,
     AttackType::TDM,
     AttackType::Q,
+    AttackType::SS,
+    AttackType::SD,
+    AttackType::ST,
+    AttackType::SSM,
+    AttackType::SDM,
 ];
 
-fn b2b_to_level(chain_length: i8) -> usize {
-    match chain_length {
-        0 => 0,
-        1..=2 => 1,
-        3..=7 => 2,
-        8..=23 => 3,
-        24..=66 => 4,
-        67..=100 => 5,
-        _ => 6,
+pub fn calc_damage(game: &GameData, rules: &GameRules, attack_type: AttackType, all_clear: bool) -> usize {
+    rules.damage.damage(
+        attack_type,
+        game.combo,
+        game.b2b,
+        rules.allow_b2b_chain,
+        all_clear,
+        game.seconds,
+    )
+}
+
+pub fn attack_type(spin: TSpinType, lines_cleared: usize, piece_type: PieceType) -> AttackType {
+    // other pieces only spin in all spin rooms, and hit as hard as the t-spin with the same lines
+    let t = piece_type == 6;
+    match (lines_cleared, spin) {
+        (0, _) => AttackType::None,
+        (4, _) => AttackType::Q,
+        (3, TSpinType::None) => AttackType::T,
+        (3, _) if t => AttackType::TT,
+        (3, _) => AttackType::ST,
+        (2, TSpinType::None) => AttackType::D,
+        (2, TSpinType::Mini) if t => AttackType::TDM,
+        (2, TSpinType::Mini) => AttackType::SDM,
+        (2, _) if t => AttackType::TD,
+        (2, _) => AttackType::SD,
+        (_, TSpinType::None) => AttackType::S,
+        (_, TSpinType::Mini) if t => AttackType::TSM,
+        (_, TSpinType::Mini) => AttackType::SSM,
+        (_, _) if t => AttackType::TS,
+        (_, _) => AttackType::SS,
     }
 }
 
-pub fn calc_damage(game: &mut GameData, attack_type: AttackType, num_cleared: usize) -> usize {
-    if num_cleared == 0 {
-        return 0;
+#[cfg(test)]
+mod versus_tests {
+    use super::*;
+    use AttackType::*;
+
+    fn damage(attack: AttackType, combo: i8, b2b: i8) -> usize {
+        DamageModel::default().damage(attack, combo, b2b, true, false, 0.0)
     }
 
-    let combo = game.combo as usize;
-    let b2b = game.b2b;
-    let all_clear_damage = 10 * game.all_clear as usize;
+    #[test]
+    fn test_base_damage() {
+        let sent: Vec<usize> = [S, D, T, Q, TSM, TDM, TS, TD, TT].iter().map(|&a| damage(a, 0, 0)).collect();
+        assert_eq!(sent, [0, 1, 2, 4, 0, 1, 2, 4, 6]);
+    }
 
-    let without_b2b = (combo + 4) * ATTACK_TYPE_CONVERSION[attack_type_to_index(attack_type)] / 4
-        + WEIRD_DAMAGE_TABLE[attack_type_to_index_special(attack_type)][combo];
+    #[test]
+    fn test_b2b() {
+        // +1 for b2b x1 and x2, +2 from x3 to x7, +3 from x8
+        let quads: Vec<usize> = [1, 2, 3, 7, 8, 24].iter().map(|&b2b| damage(Q, 0, b2b)).collect();
+        assert_eq!(quads, [5, 5, 6, 6, 7, 8]);
+        assert_eq!(damage(TD, 0, 4), 6);
+        // only difficult clears keep the bonus
+        assert_eq!(damage(D, 0, 4), 1);
 
-    (without_b2b as f32 * BACK_TO_BACK_CONVERSION[b2b_to_level(b2b)]) as usize + all_clear_damage
-}
+        let charging: Vec<usize> = [0, 1, 4, 24]
+            .iter()
+            .map(|&b2b| DamageModel::default().damage(Q, 0, b2b, false, false, 0.0))
+            .collect();
+        assert_eq!(charging, [4, 5, 5, 5]);
+    }
+
+    #[test]
+    fn test_combo() {
+        assert_eq!(damage(D, 1, 0), 1);
+        assert_eq!(damage(TD, 1, 0), 5);
+        assert_eq!(damage(Q, 4, 0), 8);
+        // singles pick up a line or two from long combos
+        let singles: Vec<usize> = [1, 2, 5, 10, 17].iter().map(|&combo| damage(S, combo, 0)).collect();
+        assert_eq!(singles, [0, 1, 1, 2, 3]);
+
+        let classic = DamageModel {
+            combo_table: ComboTable::Classic,
+            ..Default::default()
+        };
+        assert_eq!(classic.damage(D, 3, 0, true, false, 0.0), 3);
+        assert_eq!(classic.damage(S, 20, 0, true, false, 0.0), 5);
+        let none = DamageModel {
+            combo_table: ComboTable::None,
+            ..Default::default()
+        };
+        assert_eq!(none.damage(TD, 6, 0, true, false, 0.0), 4);
+    }
+
+    #[test]
+    fn test_surge() {
+        let model = DamageModel::default();
+        let surges: Vec<usize> = [1, 4, 5, 6, 10].iter().map(|&b2b| model.surge(b2b, 0.0)).collect();
+        assert_eq!(surges, [0, 0, 4, 5, 9]);
+    }
 
-pub fn attack_type(t_spin: TSpinType, lines_cleared: usize) -> AttackType {
-    match lines_cleared {
-        0 => AttackType::None,
-        4 => AttackType::Q,
-        3 => match t_spin {
-            TSpinType::None => AttackType::T,
-            _ => AttackType::TT,
-        },
-        2 => match t_spin {
-            TSpinType::None => AttackType::D,
-            TSpinType::Mini => AttackType::TDM,
-            _ => AttackType::TD,
-        },
-        _ => match t_spin {
-            TSpinType::None => AttackType::S,
-            TSpinType::Mini => AttackType::TSM,
-            _ => AttackType::TS,
-        },
+    #[test]
+    fn test_multiplier() {
+        let model = DamageModel {
+            garbage_multiplier: 1.5,
+            garbage_increase: 0.25,
+            margin_time: 60.0,
+            all_clear_bonus: 5,
+            ..Default::default()
+        };
+        assert_eq!(model.damage(TD, 0, 0, true, false, 0.0), 6);
+        assert_eq!(model.multiplier(30.0), 1.5);
+        assert_eq!(model.damage(TD, 0, 0, true, false, 62.0), 8);
+        // the all clear bonus isn't multiplied
+        assert_eq!(model.damage(D, 0, 0, true, true, 0.0), 6);
     }
 }
@@ -0,0 +1,247 @@
+#![allow(dead_code)]
+
+use crate::constants::board_constants::BOARD_WIDTH;
+use crate::game::Game;
+use crate::players::Player;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Winner {
+    PlayerOne,
+    PlayerTwo,
+    Draw,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlayerStats {
+    pub pieces: usize,
+    pub attack: usize,
+    pub cancelled: usize,
+    pub lines: usize,
+    // real time spent deciding, the rates below are measured against it
+    pub think_time: Duration,
+}
+
+impl PlayerStats {
+    fn minutes(&self) -> f32 {
+        self.think_time.as_secs_f32().max(f32::EPSILON) / 60.0
+    }
+
+    pub fn think_per_piece(&self) -> Duration {
+        self.think_time / self.pieces.max(1) as u32
+    }
+
+    // attack per piece doesn't depend on the machine
+    pub fn app(&self) -> f32 {
+        self.attack as f32 / self.pieces.max(1) as f32
+    }
+
+    pub fn apm(&self) -> f32 {
+        self.attack as f32 / self.minutes()
+    }
+
+    pub fn pps(&self) -> f32 {
+        self.pieces as f32 / (self.minutes() * 60.0)
+    }
+
+    pub fn vs(&self) -> f32 {
+        // cancelled garbage stands in for garbage cleared
+        (self.attack + self.cancelled) as f32 * 100.0 / (self.minutes() * 60.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchResult {
+    pub winner: Winner,
+    pub stats: [PlayerStats; 2],
+}
+
+pub struct Match<A: Player, B: Player> {
+    pub player_one: A,
+    pub player_two: B,
+    max_pieces: usize,
+    garbage_rng: StdRng,
+    stats: [PlayerStats; 2],
+}
+
+impl<A: Player, B: Player> Match<A, B> {
+    pub fn new(seed: usize, mut player_one: A, mut player_two: B, max_pieces: usize) -> Self {
+        // both sides get the same queue
+        let rules = player_one.get_game().get_game_rules().clone();
+        *player_one.get_game_mut() = Game::from_rules(Some(seed), rules);
+        let rules = player_two.get_game().get_game_rules().clone();
+        *player_two.get_game_mut() = Game::from_rules(Some(seed), rules);
+
+        Self {
+            player_one,
+            player_two,
+            max_pieces,
+            garbage_rng: StdRng::seed_from_u64(seed as u64),
+            stats: Default::default(),
+        }
+    }
+
+    pub fn play(mut self) -> MatchResult {
+        let mut winner = Winner::Draw;
+        let started = Instant::now();
+
+        'rounds: for round in 0..self.max_pieces {
+            // the first mover swaps every round so neither side is always a piece ahead
+            let order = if round % 2 == 0 { [0, 1] } else { [1, 0] };
+            for side in order {
+                if !self.turn(side, started.elapsed().as_secs_f32()) {
+                    winner = if side == 0 { Winner::PlayerTwo } else { Winner::PlayerOne };
+                    break 'rounds;
+                }
+            }
+        }
+
+        MatchResult {
+            winner,
+            stats: self.stats,
+        }
+    }
+
+    fn turn(&mut self, side: usize, seconds: f32) -> bool {
+        if side == 0 {
+            play_turn(
+                &mut self.player_one,
+                &mut self.player_two,
+                &mut self.stats[0],
+                &mut self.garbage_rng,
+                seconds,
+            )
+        } else {
+            play_turn(
+                &mut self.player_two,
+                &mut self.player_one,
+                &mut self.stats[1],
+                &mut self.garbage_rng,
+                seconds,
+            )
+        }
+    }
+}
+
+// places one piece for a side and sends its attack across, false if that side topped out
+fn play_turn<P: Player, Q: Player>(
+    player: &mut P,
+    opponent: &mut Q,
+    stats: &mut PlayerStats,
+    garbage_rng: &mut StdRng,
+    seconds: f32,
+) -> bool {
+    // both games run on the match's clock
+    player.get_game_mut().game_data.seconds = seconds;
+    let now = Instant::now();
+    let moved = player.make_move();
+    stats.think_time += now.elapsed();
+    // a hard drop runs Board::top_out through Game::set_piece and sets game over on a lock out
+    if !moved || player.get_game().get_game_over() {
+        return false;
+    }
+
+    let data = &player.get_game().game_data;
+    stats.pieces = data.pieces_placed;
+    stats.lines = data.lines_cleared;
+    stats.attack += data.last_sent as usize;
+    stats.cancelled += data.last_cancelled as usize;
+
+    let sent = data.last_sent as usize;
+    if sent > 0 {
+        let hole = garbage_rng.gen_range(0..BOARD_WIDTH);
+        opponent.get_game_mut().receive_garbage(sent, hole);
+    }
+    true
+}
+
+#[cfg(test)]
+mod versus_match_tests {
+    use super::*;
+    use crate::constants::bot_constants::Command;
+    use crate::constants::types::CommandList;
+    use crate::piece::Piece;
+    use crate::point_vector::Point;
+
+    struct Dropper {
+        game: Game,
+    }
+
+    impl Default for Dropper {
+        fn default() -> Self {
+            Self {
+                game: Game::new(Some(1)),
+            }
+        }
+    }
+
+    impl Player for Dropper {
+        fn get_game(&self) -> &Game {
+            &self.game
+        }
+
+        fn get_game_mut(&mut self) -> &mut Game {
+            &mut self.game
+        }
+
+        fn get_next_move(&mut self) -> CommandList {
+            vec![Command::HardDrop]
+        }
+    }
+
+    #[test]
+    fn test_top_out() {
+        let result = Match::new(15, Dropper::default(), Dropper::default(), 100).play();
+        // both stack in the middle identically, whoever moves first in the deciding round tops out
+        assert_ne!(result.winner, Winner::Draw);
+        let (loser, other) = match result.winner {
+            Winner::PlayerTwo => (&result.stats[0], &result.stats[1]),
+            _ => (&result.stats[1], &result.stats[0]),
+        };
+        assert_eq!(loser.pieces, other.pieces);
+
+        let result = Match::new(15, Dropper::default(), Dropper::default(), 2).play();
+        assert_eq!(result.winner, Winner::Draw);
+    }
+
+    #[test]
+    fn test_send_garbage() {
+        let mut sender = Dropper::default();
+        let mut receiver = Dropper::default();
+        for row in 0..4 {
+            sender.game.board.set_row(row, vec![true, true, true, true, true, true, true, true, true, false]);
+        }
+        // not an all clear
+        sender.game.board.add(4, 0);
+        sender.game.set_active_piece(Piece {
+            piece_type: 4,
+            rotation_state: 1,
+            center: Point(10, 9),
+            last_kick: 0,
+        });
+
+        let mut stats = PlayerStats::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(play_turn(&mut sender, &mut receiver, &mut stats, &mut rng, 0.0));
+        assert_eq!(stats.attack, 4);
+        assert_eq!(stats.app(), 4.0);
+        assert!(stats.think_time > Duration::ZERO && stats.pps() > 0.0);
+        assert_eq!(receiver.game.get_pending_garbage(), 4);
+
+        // a single sends nothing back, but clearing still holds the garbage off
+        receiver.game.board.set_row(0, vec![true, true, true, true, true, true, false, false, false, false]);
+        receiver.game.board.add(1, 0);
+        receiver.game.set_active_piece(Piece {
+            piece_type: 4,
+            rotation_state: 0,
+            center: Point(10, 7),
+            last_kick: 0,
+        });
+        assert!(play_turn(&mut receiver, &mut sender, &mut PlayerStats::default(), &mut rng, 0.0));
+        assert_eq!(receiver.game.get_pending_garbage(), 4);
+        assert_eq!(sender.game.get_pending_garbage(), 0);
+    }
+}
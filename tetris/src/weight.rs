@@ -1,20 +1,44 @@
 use polynomial::Polynomial;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-#[derive(Clone)]
+// named profiles are looked up here as <name>.toml or <name>.json
+pub const PROFILE_DIR: &str = "weights";
+
+// missing fields in a file fall back to the default weights
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Weights {
+    #[serde(with = "coefficients")]
     pub height_weight: Polynomial<f32>,
 
+    #[serde(with = "coefficients")]
     pub adjacent_height_differences_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub total_height_difference_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub num_hole_total_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub num_hole_weighted_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub cell_covered_weight: Polynomial<f32>,
 
+    #[serde(with = "coefficients")]
     pub t_slot_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub b2b_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub combo_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub damage_weight: Polynomial<f32>,
+    #[serde(with = "coefficients")]
     pub clear_weight: Polynomial<f32>,
+
+    // after the rest, so coefficient lists saved before it was added still line up
+    #[serde(with = "coefficients")]
+    pub t_slot_lines_weight: Polynomial<f32>,
 }
 
 impl Default for Weights {
@@ -32,6 +56,168 @@ impl Default for Weights {
             combo_weight: Polynomial::new(vec![0.0, 8.0, -4.0]),
             damage_weight: Polynomial::new(vec![0.0, 28.0, -8.0]),
             clear_weight: Polynomial::new(vec![0.0, 49.0, -7.0]),
+
+            t_slot_lines_weight: Polynomial::new(vec![0.0, -40.0]),
+        }
+    }
+}
+
+impl Weights {
+    // coefficient vectors of every weight, in field order
+    pub fn coefficients(&self) -> Vec<Vec<f32>> {
+        self.polynomials()
+            .iter()
+            .map(|polynomial| polynomial.data().to_vec())
+            .collect()
+    }
+
+    pub fn from_coefficients(coefficients: &[Vec<f32>]) -> Self {
+        let mut out = Self::default();
+        for (polynomial, data) in out.polynomials_mut().into_iter().zip(coefficients) {
+            *polynomial = Polynomial::new(data.clone());
+        }
+        out
+    }
+
+    // files
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        if is_toml(path) {
+            toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(serde_json::from_str(&text)?)
         }
     }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = if is_toml(path) {
+            toml::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        fs::write(path, text)
+    }
+
+    // a path to a weight file, or the name of a profile in PROFILE_DIR
+    pub fn from_name_or_path(name: &str) -> io::Result<Self> {
+        let path = Path::new(name);
+        if path.is_file() {
+            return Self::load(path);
+        }
+        match Self::profile_path(name) {
+            Some(path) => Self::load(&path),
+            None if name == "default" => Ok(Self::default()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no weight file or profile named {}", name),
+            )),
+        }
+    }
+
+    fn profile_path(name: &str) -> Option<PathBuf> {
+        ["toml", "json"]
+            .iter()
+            .map(|extension| Path::new(PROFILE_DIR).join(format!("{}.{}", name, extension)))
+            .find(|path| path.is_file())
+    }
+
+    fn polynomials(&self) -> [&Polynomial<f32>; 12] {
+        [
+            &self.height_weight,
+            &self.adjacent_height_differences_weight,
+            &self.total_height_difference_weight,
+            &self.num_hole_total_weight,
+            &self.num_hole_weighted_weight,
+            &self.cell_covered_weight,
+            &self.t_slot_weight,
+            &self.b2b_weight,
+            &self.combo_weight,
+            &self.damage_weight,
+            &self.clear_weight,
+            &self.t_slot_lines_weight,
+        ]
+    }
+
+    fn polynomials_mut(&mut self) -> [&mut Polynomial<f32>; 12] {
+        [
+            &mut self.height_weight,
+            &mut self.adjacent_height_differences_weight,
+            &mut self.total_height_difference_weight,
+            &mut self.num_hole_total_weight,
+            &mut self.num_hole_weighted_weight,
+            &mut self.cell_covered_weight,
+            &mut self.t_slot_weight,
+            &mut self.b2b_weight,
+            &mut self.combo_weight,
+            &mut self.damage_weight,
+            &mut self.clear_weight,
+            &mut self.t_slot_lines_weight,
+        ]
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "toml")
+}
+
+// polynomials are stored as their coefficient list, lowest degree first
+mod coefficients {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(polynomial: &Polynomial<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+        polynomial.data().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Polynomial<f32>, D::Error> {
+        Ok(Polynomial::new(Vec::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod weight_tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_file() {
+        let json: Weights = serde_json::from_str(r#"{"b2b_weight": [1.0, 2.0]}"#).unwrap();
+        assert_eq!(json.b2b_weight.data(), &[1.0, 2.0]);
+        assert_eq!(json.height_weight.data(), Weights::default().height_weight.data());
+
+        let toml: Weights = toml::from_str("combo_weight = [0.0, 3.0]").unwrap();
+        assert_eq!(toml.combo_weight.data(), &[0.0, 3.0]);
+        assert_eq!(toml.coefficients()[0], Weights::default().coefficients()[0]);
+    }
+
+    #[test]
+    fn test_old_coefficients() {
+        // genomes saved before the t slot lines weight keep their meaning, it takes its default
+        let mut old = Weights::default().coefficients();
+        old.pop();
+        old[10] = vec![1.0, 2.0];
+        let weights = Weights::from_coefficients(&old);
+        assert_eq!(weights.clear_weight.data(), &[1.0, 2.0]);
+        assert_eq!(weights.t_slot_lines_weight.data(), Weights::default().t_slot_lines_weight.data());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let weights = Weights {
+            clear_weight: Polynomial::new(vec![1.5, -2.0, 0.25]),
+            ..Default::default()
+        };
+
+        for extension in ["json", "toml"] {
+            let path = std::env::temp_dir().join(format!("ivfish_weights_test_{}.{}", std::process::id(), extension));
+            weights.save(&path).unwrap();
+            let loaded = Weights::from_name_or_path(path.to_str().unwrap()).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.coefficients(), weights.coefficients());
+        }
+    }
+
+    #[test]
+    fn test_profiles() {
+        assert!(Weights::from_name_or_path("default").is_ok());
+        assert!(Weights::from_name_or_path("no such profile").is_err());
+    }
 }